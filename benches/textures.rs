@@ -0,0 +1,119 @@
+//! Compares looking textures up by name through the `AssetCache` for every wall column and
+//! sprite, which is what the renderer used to do, against resolving them to handles once.
+//!
+//! This is a stand-in, not the game's renderer or `TextureRegistry`: the game is a binary so
+//! benches can't import it. `Texture`, the per-column work and the handle table below are
+//! small copies that only keep the lookup pattern of each side, so the numbers show what the
+//! lookups cost rather than how fast a real frame draws.
+//! Run with `cargo bench --bench textures`
+
+use std::{hint::black_box, time::Instant};
+
+use assets_manager::{
+    loader::{ImageLoader, LoadFrom},
+    Asset, AssetCache,
+};
+use image::DynamicImage;
+
+const WIDTH: usize = 384;
+const HEIGHT: usize = 216;
+const SPRITES: usize = 8;
+const FRAMES: u32 = 500;
+
+/// Walls alternate between these so lookups can't all hit the same entry
+const WALLS: [&str; 2] = ["wall", "exit"];
+const SPRITE: &str = "generator";
+
+struct Texture {
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+impl From<DynamicImage> for Texture {
+    fn from(img: DynamicImage) -> Self {
+        Self {
+            width: img.width(),
+            height: img.height(),
+            bytes: img.into_rgba8().into_raw(),
+        }
+    }
+}
+
+impl Asset for Texture {
+    const EXTENSIONS: &'static [&'static str] = &["png"];
+    type Loader = LoadFrom<DynamicImage, ImageLoader>;
+}
+
+/// Reads one screen column worth of texels, standing in for the drawing done per lookup
+fn column(tex: &Texture, x: usize) -> u32 {
+    let u = x as u32 % tex.width;
+    (0..HEIGHT as u32)
+        .map(|y| {
+            let v = y * tex.height / HEIGHT as u32;
+            tex.bytes[((v * tex.width + u) * 4) as usize] as u32
+        })
+        .sum()
+}
+
+fn by_name(cache: &AssetCache) -> u32 {
+    let mut sum = 0;
+    let mut draw = |name: &str, x: usize| {
+        let handle = cache
+            .load::<Texture>(&format!("textures.{name}"))
+            .expect("missing texture");
+        sum += column(&handle.read(), x);
+    };
+    for x in 0..WIDTH {
+        draw(WALLS[x % WALLS.len()], x);
+    }
+    for x in 0..SPRITES {
+        draw(SPRITE, x);
+    }
+    sum
+}
+
+fn by_handle(textures: &[Texture], walls: [usize; 2], sprite: usize) -> u32 {
+    let mut sum = 0;
+    for x in 0..WIDTH {
+        sum += column(&textures[walls[x % walls.len()]], x);
+    }
+    for x in 0..SPRITES {
+        sum += column(&textures[sprite], x);
+    }
+    sum
+}
+
+/// Milliseconds per frame
+fn time(mut frame: impl FnMut() -> u32) -> f64 {
+    // Warm up so both start with everything loaded and cached
+    black_box(frame());
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        black_box(frame());
+    }
+    start.elapsed().as_secs_f64() * 1000. / FRAMES as f64
+}
+
+fn main() {
+    let cache = AssetCache::new(concat!(env!("CARGO_MANIFEST_DIR"), "/assets")).unwrap();
+
+    let names = [WALLS[0], WALLS[1], SPRITE];
+    let textures: Vec<Texture> = names
+        .iter()
+        .map(|name| {
+            let path = format!("{}/assets/textures/{name}.png", env!("CARGO_MANIFEST_DIR"));
+            image::open(path).expect("missing texture").into()
+        })
+        .collect();
+
+    let before = time(|| by_name(&cache));
+    let after = time(|| by_handle(&textures, [0, 1], 2));
+    assert_eq!(by_name(&cache), by_handle(&textures, [0, 1], 2));
+
+    println!("{WIDTH} columns and {SPRITES} sprites, {FRAMES} frames");
+    println!("names through AssetCache: {before:.4} ms per frame");
+    println!("handles:                  {after:.4} ms per frame");
+    println!("{:.1}x faster", before / after);
+}
@@ -1,45 +1,116 @@
 use std::collections::HashMap;
 
 use crate::{
+    animation::{Animator, Clip},
     astar,
     components::{Monster, MonsterState},
-    map,
+    graphics::TextureRegistry,
+    map, player,
     prelude::*,
-    sound,
+    sound, spawner,
     state::game::{add_event, Camera},
-    ticks,
 };
 use bevy_ecs::prelude::*;
 use rand::Rng;
 
-pub const ATTACK_TIME: u32 = (FPS as f32 * 2.5) as u32;
+pub const ATTACK_TIME: f32 = 2.5;
 
 const SEEK_TIME: f32 = 10.;
 const ATTACK_RANGE: f32 = 4.;
+/// How fast monsters move while they aren't chasing anything
+pub const WALK_SPEED: f32 = 0.125;
 const AGGRO_SPEED_MULTIPLIER: f32 = 1.15;
 
+const MONSTER_FRAME: UVec2 = UVec2::splat(64);
+pub static MONSTER_WALK: Clip = Clip {
+    texture: "monster_walk",
+    frame_size: MONSTER_FRAME,
+    frames: 4,
+    frame_time: 0.2,
+    looping: true,
+    directions: 8,
+};
+pub static MONSTER_ATTACK: Clip = Clip {
+    texture: "monster_attack",
+    frame_size: MONSTER_FRAME,
+    frames: 4,
+    frame_time: 0.1,
+    looping: true,
+    directions: 8,
+};
+pub static MONSTER_FLEE: Clip = Clip {
+    texture: "monster_flee",
+    frame_size: MONSTER_FRAME,
+    frames: 4,
+    frame_time: 0.12,
+    looping: true,
+    directions: 8,
+};
+
 struct ReachedTarget {
     nav_entity: Entity,
     target: Vec2,
 }
 
+/// Requests a monster to crawl out of a nest away from the player
+pub struct SummonMonster;
+
 pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
     add_event::<ReachedTarget>(world, schedule);
+    add_event::<SummonMonster>(world, schedule);
     schedule.add_systems((
+        summon_from_nest,
         traverse_path,
         navigate,
         monster_rest_countdown,
         monster_wander,
         monster_rest,
         play_monster_sound,
+        hear_player,
+        spot_flashlight,
         set_target,
         attack,
+        monster_speed,
         flee,
+        animate_monster,
     ));
 }
 
+fn summon_from_nest(
+    mut cmd: Commands,
+    mut textures: ResMut<TextureRegistry>,
+    mut event_reader: EventReader<SummonMonster>,
+    nest_query: Query<&components::Transform, With<components::Nest>>,
+    target_query: Query<&components::Transform, With<components::MonsterTarget>>,
+) {
+    for _ in event_reader.iter() {
+        // Pick the nest furthest away from the closest target so monsters never pop in next to the player
+        let nest = nest_query.iter().max_by(|a, b| {
+            let closest = |trans: &components::Transform| {
+                target_query
+                    .iter()
+                    .map(|target| target.pos.distance_squared(trans.pos))
+                    .fold(f32::MAX, f32::min)
+            };
+            closest(a).total_cmp(&closest(b))
+        });
+
+        let Some(nest) = nest else {
+            warn!("A monster was summoned but there are no nests to crawl out of");
+            return;
+        };
+
+        let monster = spawner::spawn_monster(&mut cmd, &mut textures, *nest);
+        cmd.entity(monster).insert(Monster {
+            state: MonsterState::Wander,
+            attack_time: ATTACK_TIME,
+        });
+    }
+}
+
 fn traverse_path(
     mut event_writer: EventWriter<ReachedTarget>,
+    map: Res<map::Map>,
     mut nav_query: Query<(
         Entity,
         &components::Transform,
@@ -60,9 +131,8 @@ fn traverse_path(
         // Get closest node position
         if let Some(pos) = nav.path.get(*idx) {
             let pos = *pos + 0.5;
-            let dir = pos - trans.pos;
 
-            movement.set_velocity(dir);
+            movement.set_velocity(steer(&map, trans.pos, pos));
 
             if pos.distance_squared(trans.pos) < MIN_DIST {
                 if (pos - 0.5) == move_to {
@@ -85,6 +155,21 @@ fn traverse_path(
     }
 }
 
+/// Direction to move towards `target`, going along a single axis first
+/// when heading straight there would clip the corner of a wall
+fn steer(map: &map::Map, from: Vec2, target: Vec2) -> Vec2 {
+    let is_wall = |pos: Vec2| map.get_tile(pos.x as u32, pos.y as u32) != Some(&map::Tile::Empty);
+    let dir = target - from;
+
+    if is_wall(vec2(target.x, from.y)) {
+        return vec2(0., dir.y);
+    }
+    if is_wall(vec2(from.x, target.y)) {
+        return vec2(dir.x, 0.);
+    }
+    dir
+}
+
 fn navigate(
     map: Res<crate::map::Map>,
     mut query: Query<(&components::Transform, &mut components::Navigator)>,
@@ -96,29 +181,30 @@ fn navigate(
     }
 }
 
-fn monster_rest_countdown(mut query: Query<&mut Monster>) {
+fn monster_rest_countdown(time: Res<Time>, mut query: Query<&mut Monster>) {
     for mut monster in query.iter_mut() {
-        let MonsterState::Rest(ticks) = monster.state else {
+        let MonsterState::Rest(seconds) = monster.state else {
             continue;
         };
 
-        if ticks == 0 {
+        if seconds <= 0. {
             monster.state = MonsterState::Wander;
             return;
         }
-        monster.state = MonsterState::Rest(ticks - 1);
+        monster.state = MonsterState::Rest(seconds - time.delta());
         monster.attack_time = ATTACK_TIME;
     }
 }
 
 fn play_monster_sound(
+    time: Res<Time>,
     mut sounds: ResMut<sound::SoundQueue>,
     cam: Res<Camera>,
     query: Query<(&components::Transform, &Monster)>,
-    mut snd_timer: Local<u32>,
+    mut snd_timer: Local<f32>,
 ) {
-    if *snd_timer != 0 {
-        *snd_timer -= 1;
+    if *snd_timer > 0. {
+        *snd_timer -= time.delta();
         return;
     }
 
@@ -140,7 +226,7 @@ fn play_monster_sound(
         )
     }
     // Play sound every 1.25 seconds
-    *snd_timer = ticks(seconds_to_play);
+    *snd_timer = seconds_to_play;
 }
 
 fn monster_wander(mut query: Query<(&Monster, &mut components::Navigator)>, map: Res<map::Map>) {
@@ -173,22 +259,114 @@ fn monster_rest(mut event_reader: EventReader<ReachedTarget>, mut query: Query<&
             continue;
         }
         let rest_time = rand::thread_rng().gen_range(2..6);
-        monster.state = MonsterState::Rest(ticks(rest_time as f32));
+        monster.state = MonsterState::Rest(rest_time as f32);
+    }
+}
+
+fn hear_player(
+    mut query: Query<(&components::Transform, &mut Monster)>,
+    player_query: Query<
+        (
+            Entity,
+            &components::Transform,
+            &components::Movement,
+            &components::Player,
+        ),
+        Without<Monster>,
+    >,
+) {
+    for (trans, mut monster) in query.iter_mut() {
+        match monster.state {
+            MonsterState::Rest(_) | MonsterState::Wander => (),
+            _ => continue,
+        }
+
+        for (ent, player_trans, player_movement, player) in player_query.iter() {
+            if player_movement.velocity() == Vec2::ZERO {
+                continue;
+            }
+
+            let noise = player.mode.noise();
+            if trans.pos.distance_squared(player_trans.pos) < noise * noise {
+                start_attack(&mut monster, ent);
+                break;
+            }
+        }
+    }
+}
+
+/// Monsters caught in a flashlight beam come for whoever is holding it
+fn spot_flashlight(
+    map: Res<map::Map>,
+    mut query: Query<(Entity, &components::Transform, &mut Monster)>,
+    player_query: Query<
+        (Entity, &components::Transform, &components::Flashlight),
+        Without<Monster>,
+    >,
+    collider_query: Query<(Entity, &components::Transform, &components::Collider)>,
+) {
+    for (ent, player_trans, flashlight) in player_query.iter() {
+        let Some(cone) = player::flashlight_cone(player_trans.pos, player_trans.dir, flashlight)
+        else {
+            continue;
+        };
+
+        for (monster_ent, trans, mut monster) in query.iter_mut() {
+            match monster.state {
+                MonsterState::Rest(_) | MonsterState::Wander => (),
+                _ => continue,
+            }
+
+            let to_monster = trans.pos - player_trans.pos;
+            if cone.level(trans.pos) <= 0.
+                || physics::raycast(&map, cone.pos, to_monster, 1.).is_some()
+            {
+                continue;
+            }
+
+            // Anything solid in between keeps the monster in its shadow
+            let colliders = collider_query
+                .iter()
+                .filter(|(col_ent, ..)| *col_ent != ent);
+            let first_hit = physics::raycast_entities(cone.pos, to_monster, 1., colliders);
+            if first_hit.is_some_and(|hit| hit.entity != monster_ent) {
+                continue;
+            }
+
+            start_attack(&mut monster, ent);
+        }
+    }
+}
+
+fn start_attack(monster: &mut Monster, target: Entity) {
+    monster.state = MonsterState::Attack(target);
+}
+
+/// Speeds monsters up while they chase something. Worked out from the state every tick
+/// instead of scaled on the way in and out, so coming back for more can't stack it up
+fn monster_speed(mut query: Query<(&Monster, &mut components::Movement)>) {
+    for (monster, mut movement) in query.iter_mut() {
+        let speed = match monster.state {
+            MonsterState::Attack(_) => WALK_SPEED * AGGRO_SPEED_MULTIPLIER,
+            _ => WALK_SPEED,
+        };
+        movement.set_speed(speed);
     }
 }
 
 fn set_target(
-    mut query: Query<(&mut Monster, &mut components::Movement)>,
+    time: Res<Time>,
+    mut query: Query<&mut Monster>,
     target_query: Query<Entity, With<components::MonsterTarget>>,
-    mut timer: Local<u32>,
+    mut timer: Local<f32>,
 ) {
-    if *timer != 0 {
-        *timer -= 1;
+    if *timer > 0. {
+        *timer -= time.delta();
         return;
     }
 
     let targets: Vec<Entity> = target_query.iter().collect();
-    for (mut monster, mut movement) in query.iter_mut() {
+    for mut monster in query.iter_mut() {
         let MonsterState::Wander = monster.state else {
             continue;
         };
@@ -197,22 +375,20 @@ fn set_target(
         }
 
         if let Some(target) = targets.get(rand::thread_rng().gen_range(0..targets.len())) {
-            monster.state = MonsterState::Attack(*target);
+            start_attack(&mut monster, *target);
         }
-
-        let speed = movement.speed() * AGGRO_SPEED_MULTIPLIER;
-        movement.set_speed(speed);
     }
 
-    *timer = ticks(SEEK_TIME);
+    *timer = SEEK_TIME;
 }
 
 fn attack(
+    time: Res<Time>,
     mut sounds: ResMut<sound::SoundQueue>,
+    mut shake_writer: EventWriter<player::ShakeScreen>,
     mut query: Query<(
         &components::Transform,
         &mut Monster,
-        &mut components::Movement,
         &mut components::Navigator,
     )>,
     mut target_query: Query<
@@ -225,7 +401,7 @@ fn attack(
         Without<components::Monster>,
     >,
 ) {
-    'outer: for (trans, mut monster, mut movement, mut nav) in query.iter_mut() {
+    'outer: for (trans, mut monster, mut nav) in query.iter_mut() {
         match monster.state {
             MonsterState::Flee(_) | MonsterState::Rest(_) => continue,
             _ => (),
@@ -234,7 +410,7 @@ fn attack(
         for (ent, target_trans, mut target, movement) in target_query.iter_mut() {
             if trans.pos.distance_squared(target_trans.pos) < ATTACK_RANGE {
                 monster.state = MonsterState::Attack(ent);
-                if monster.attack_time != 0 {
+                if monster.attack_time > 0. {
                     if let Some(mut movement) = movement {
                         movement.set_velocity(Vec2::ZERO);
                     }
@@ -248,8 +424,9 @@ fn attack(
                                 ..Default::default()
                             },
                         );
+                        shake_writer.send(player::ShakeScreen { trauma: 1. });
                     }
-                    monster.attack_time -= 1;
+                    monster.attack_time -= time.delta();
                     continue 'outer;
                 }
                 target.is_dead = true;
@@ -268,10 +445,7 @@ fn attack(
         nav.move_to = Some(target_trans.pos);
 
         if target.is_dead {
-            monster.state = MonsterState::Rest(ticks(15.));
-
-            let speed = movement.speed() / AGGRO_SPEED_MULTIPLIER;
-            movement.set_speed(speed);
+            monster.state = MonsterState::Rest(15.);
         }
     }
 }
@@ -284,3 +458,166 @@ fn flee(mut query: Query<(&mut components::Navigator, &components::Monster)>) {
         nav.move_to = Some(pos);
     }
 }
+
+/// Faces monsters where they're going and plays the animation matching their state
+fn animate_monster(
+    mut textures: ResMut<TextureRegistry>,
+    mut query: Query<(
+        &mut components::Transform,
+        &components::Movement,
+        &Monster,
+        &mut Animator,
+    )>,
+) {
+    for (mut trans, movement, monster, mut animator) in query.iter_mut() {
+        if movement.velocity() != Vec2::ZERO {
+            trans.dir = movement.velocity().normalize();
+        }
+
+        let clip = match monster.state {
+            MonsterState::Rest(_) | MonsterState::Wander => &MONSTER_WALK,
+            MonsterState::Attack(_) => &MONSTER_ATTACK,
+            MonsterState::Flee(_) => &MONSTER_FLEE,
+        };
+        animator.play(clip, &mut textures);
+        // Stand still on the first frame while resting
+        animator.paused = matches!(monster.state, MonsterState::Rest(_));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lets a monster notice the player `cycles` times, running away in between,
+    /// and returns how fast it was every time it gave chase
+    fn chase_speeds(flashlight: bool, cycles: usize) -> Vec<f32> {
+        let mut world = World::default();
+        world.insert_resource(map::Map::new(8, 3));
+
+        let mut player_movement = components::Movement::with_speed(1.);
+        player_movement.set_velocity(Vec2::X * (!flashlight as u8 as f32));
+        world.spawn((
+            components::Transform {
+                pos: vec2(1.5, 1.5),
+                dir: Vec2::X,
+                ..Default::default()
+            },
+            player_movement,
+            components::Player::default(),
+            components::Flashlight {
+                on: flashlight,
+                charge: 10.,
+                brightness: 1.,
+            },
+        ));
+        let monster = world
+            .spawn((
+                components::Transform {
+                    pos: vec2(4.5, 1.5),
+                    ..Default::default()
+                },
+                Monster {
+                    state: MonsterState::Wander,
+                    attack_time: ATTACK_TIME,
+                },
+                components::Movement::with_speed(WALK_SPEED),
+            ))
+            .id();
+
+        let mut schedule = Schedule::new();
+        schedule.add_systems((hear_player, spot_flashlight, monster_speed).chain());
+
+        let mut speeds = Vec::new();
+        for _ in 0..cycles {
+            schedule.run(&mut world);
+            let monster_state = &world.get::<Monster>(monster).unwrap().state;
+            assert!(matches!(monster_state, MonsterState::Attack(_)));
+            speeds.push(world.get::<components::Movement>(monster).unwrap().speed());
+
+            // Gets hit, runs off and calms down again
+            world.get_mut::<Monster>(monster).unwrap().state = MonsterState::Flee(Vec2::ZERO);
+            schedule.run(&mut world);
+            assert_eq!(
+                world.get::<components::Movement>(monster).unwrap().speed(),
+                WALK_SPEED
+            );
+            world.get_mut::<Monster>(monster).unwrap().state = MonsterState::Wander;
+        }
+        speeds
+    }
+
+    #[test]
+    fn chasing_again_doesnt_get_faster() {
+        let speeds = chase_speeds(false, 3);
+        assert!(speeds[0] > WALK_SPEED);
+        assert!(speeds.iter().all(|speed| *speed == speeds[0]), "{speeds:?}");
+    }
+
+    #[test]
+    fn props_hide_monsters_from_the_flashlight() {
+        let mut world = World::default();
+        world.insert_resource(map::Map::new(8, 3));
+        let collider = || components::Collider {
+            size: Vec2::splat(0.5),
+            ..Default::default()
+        };
+        world.spawn((
+            components::Transform {
+                pos: vec2(1.5, 1.5),
+                dir: Vec2::X,
+                ..Default::default()
+            },
+            components::Flashlight {
+                on: true,
+                charge: 10.,
+                brightness: 1.,
+            },
+            collider(),
+        ));
+        let monster = world
+            .spawn((
+                components::Transform {
+                    pos: vec2(5.5, 1.5),
+                    ..Default::default()
+                },
+                Monster {
+                    state: MonsterState::Wander,
+                    attack_time: ATTACK_TIME,
+                },
+                collider(),
+            ))
+            .id();
+        let prop = world
+            .spawn((
+                components::Transform {
+                    pos: vec2(3.5, 1.5),
+                    ..Default::default()
+                },
+                collider(),
+            ))
+            .id();
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(spot_flashlight);
+        schedule.run(&mut world);
+        let spotted = |world: &World| {
+            matches!(
+                world.get::<Monster>(monster).unwrap().state,
+                MonsterState::Attack(_)
+            )
+        };
+        assert!(!spotted(&world));
+
+        world.despawn(prop);
+        schedule.run(&mut world);
+        assert!(spotted(&world));
+    }
+
+    #[test]
+    fn spotting_again_doesnt_get_faster() {
+        let speeds = chase_speeds(true, 3);
+        assert!(speeds[0] > WALK_SPEED);
+        assert!(speeds.iter().all(|speed| *speed == speeds[0]), "{speeds:?}");
+    }
+}
@@ -0,0 +1,157 @@
+use std::f32::consts::TAU;
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    graphics::{TextureId, TextureRegistry},
+    prelude::*,
+    state::game::{Camera, CoreSet},
+};
+
+/// Animation played from a spritesheet.
+/// Frames go left to right and every direction gets its own row, starting with the one
+/// facing the viewer and going around the entity in 360 / `directions` degree steps
+pub struct Clip {
+    pub texture: &'static str,
+    pub frame_size: UVec2,
+    pub frames: u32,
+    /// Seconds each frame is shown for
+    pub frame_time: f32,
+    pub looping: bool,
+    /// 1 for sprites that look the same from every side, 8 for ones that don't
+    pub directions: u32,
+}
+
+impl Clip {
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 * self.frame_time
+    }
+}
+
+#[derive(Component)]
+pub struct Animator {
+    clip: &'static Clip,
+    /// Spritesheet of `clip`, looked up whenever the clip changes rather than every frame
+    texture: TextureId,
+    time: f32,
+    /// Holds the current frame when set
+    pub paused: bool,
+}
+
+impl Animator {
+    pub fn new(clip: &'static Clip, textures: &mut TextureRegistry) -> Self {
+        Self {
+            clip,
+            texture: textures.id(clip.texture),
+            time: 0.,
+            paused: false,
+        }
+    }
+
+    /// Switches to another clip, starting it from the beginning.
+    /// Does nothing if the clip is already playing
+    pub fn play(&mut self, clip: &'static Clip, textures: &mut TextureRegistry) {
+        if std::ptr::eq(self.clip, clip) {
+            return;
+        }
+
+        self.clip = clip;
+        self.texture = textures.id(clip.texture);
+        self.time = 0.;
+    }
+
+    pub fn frame(&self) -> u32 {
+        let frame = (self.time / self.clip.frame_time) as u32;
+        if self.clip.looping {
+            frame % self.clip.frames
+        } else {
+            frame.min(self.clip.frames - 1)
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.clip.looping && self.time >= self.clip.duration()
+    }
+}
+
+/// Which of the `directions` rows to show for something facing `facing`, seen from `to_viewer`
+pub fn direction(facing: Vec2, to_viewer: Vec2, directions: u32) -> u32 {
+    if directions <= 1 || facing == Vec2::ZERO || to_viewer == Vec2::ZERO {
+        return 0;
+    }
+
+    let step = TAU / directions as f32;
+    let angle = facing.angle_between(to_viewer);
+    ((angle / step).round() as i32).rem_euclid(directions as i32) as u32
+}
+
+pub fn add_to_world(schedule: &mut Schedule) {
+    schedule.add_system(advance);
+    schedule.add_system(update_sprites.in_base_set(CoreSet::Last));
+}
+
+fn advance(time: Res<Time>, mut query: Query<&mut Animator>) {
+    for mut animator in query.iter_mut() {
+        if animator.paused || animator.finished() {
+            continue;
+        }
+        animator.time += time.delta();
+    }
+}
+
+fn update_sprites(
+    cam: Res<Camera>,
+    mut query: Query<(&components::Transform, &Animator, &mut components::Sprite)>,
+) {
+    for (trans, animator, mut sprite) in query.iter_mut() {
+        let clip = animator.clip;
+        let row = direction(trans.dir, cam.pos - trans.pos, clip.directions);
+
+        sprite.texture = Some(animator.texture);
+        sprite.region = Some(components::Region {
+            pos: uvec2(animator.frame(), row) * clip.frame_size,
+            size: clip.frame_size,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CLIP: Clip = Clip {
+        texture: "test",
+        frame_size: UVec2::splat(16),
+        frames: 3,
+        frame_time: 0.5,
+        looping: false,
+        directions: 8,
+    };
+
+    #[test]
+    fn faces_viewer_on_first_row() {
+        assert_eq!(direction(Vec2::X, Vec2::X, 8), 0);
+        assert_eq!(direction(Vec2::X, Vec2::NEG_X, 8), 4);
+        assert_eq!(direction(Vec2::X, Vec2::Y, 8), 2);
+        assert_eq!(direction(Vec2::X, Vec2::NEG_Y, 8), 6);
+        assert_eq!(direction(Vec2::X, vec2(1., 0.9), 8), 1);
+    }
+
+    #[test]
+    fn single_direction_always_uses_first_row() {
+        assert_eq!(direction(Vec2::X, Vec2::NEG_X, 1), 0);
+        assert_eq!(direction(Vec2::ZERO, Vec2::NEG_X, 8), 0);
+    }
+
+    #[test]
+    fn stops_on_last_frame_without_looping() {
+        let mut animator = Animator::new(&CLIP, &mut TextureRegistry::default());
+        animator.time = 1.2;
+        assert_eq!(animator.frame(), 2);
+        assert!(!animator.finished());
+
+        animator.time = 10.;
+        assert_eq!(animator.frame(), 2);
+        assert!(animator.finished());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     cmp::{Ordering, Reverse},
-    collections::{BinaryHeap, HashMap},
+    collections::{BinaryHeap, HashMap, VecDeque},
 };
 
 use crate::math::*;
@@ -91,6 +91,34 @@ pub fn navigate(map: &crate::map::Map, start: Vec2, target: Vec2) -> Vec<Vec2> {
     path
 }
 
+/// Walking distance in tiles from `start` to every reachable empty tile
+pub fn distances(map: &crate::map::Map, start: Vec2) -> HashMap<IVec2, u32> {
+    let i_start = start.as_ivec2();
+
+    let mut dist = HashMap::new();
+    dist.insert(i_start, 0);
+
+    let mut frontier = VecDeque::new();
+    frontier.push_back(i_start);
+
+    while let Some(current) = frontier.pop_front() {
+        for neighbor in neighbor_points(current) {
+            if neighbor.x.is_negative()
+                || neighbor.y.is_negative()
+                || dist.contains_key(&neighbor)
+                || map.get_tile(neighbor.x as u32, neighbor.y as u32)
+                    != Some(&crate::map::Tile::Empty)
+            {
+                continue;
+            }
+
+            dist.insert(neighbor, dist[&current] + 1);
+            frontier.push_back(neighbor);
+        }
+    }
+    dist
+}
+
 fn neighbor_points(point: IVec2) -> Vec<IVec2> {
     vec![
         ivec2(-1, 0) + point,
@@ -0,0 +1,289 @@
+use std::f32::consts::PI;
+
+use bevy_ecs::prelude::*;
+
+use crate::{map::Map, postprocess::Shake, prelude::*, state::game::Camera};
+
+/// Highest the head gets lifted while bobbing, in units
+const BOB_HEIGHT: f32 = 0.035;
+/// Steps taken per second for each unit of movement speed
+const BOB_RATE: f32 = 10.;
+/// How quickly bobbing fades in and out when starting and stopping
+const BOB_EASE: f32 = 8.;
+/// How much wider the view gets at a full sprint
+const FOV_KICK: f32 = 0.12;
+const FOV_EASE: f32 = 5.;
+/// Furthest the player can lean out to either side, in units
+const LEAN_DISTANCE: f32 = 0.35;
+const LEAN_EASE: f32 = 10.;
+/// Closest leaning gets to a wall, so the camera never ends up inside one
+const LEAN_WALL_GAP: f32 = 0.15;
+/// Radians the view twists and screen heights it jumps at full trauma
+const SHAKE_YAW: f32 = 0.04;
+const SHAKE_PITCH: f32 = 0.03;
+
+/// Changes how the camera is drawn without moving it, so effects never get in the way of
+/// walking or aiming. Applied to a copy of the camera right before rendering
+pub trait CameraModifier {
+    fn apply(&self, cam: &mut Camera);
+}
+
+/// Applies `modifiers` to `cam` in order
+pub fn apply(cam: &mut Camera, modifiers: &[&dyn CameraModifier]) {
+    for modifier in modifiers {
+        modifier.apply(cam);
+    }
+}
+
+/// Head moving up and down with every step
+#[derive(Default, Debug, Clone, Copy)]
+pub struct HeadBob {
+    /// Goes through half a turn every step
+    phase: f32,
+    /// From 0 standing still to 1 at a full sprint
+    amount: f32,
+}
+
+impl CameraModifier for HeadBob {
+    fn apply(&self, cam: &mut Camera) {
+        cam.z += self.phase.sin().abs() * BOB_HEIGHT * self.amount;
+    }
+}
+
+/// Widens the view while sprinting to make it feel faster
+#[derive(Default, Debug, Clone, Copy)]
+pub struct FovKick {
+    /// From 0 to 1
+    amount: f32,
+}
+
+impl CameraModifier for FovKick {
+    fn apply(&self, cam: &mut Camera) {
+        cam.plane *= 1. + self.amount * FOV_KICK;
+    }
+}
+
+/// Peeking around corners
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Lean {
+    /// Units to the right of the player, negative for left
+    offset: f32,
+}
+
+impl CameraModifier for Lean {
+    fn apply(&self, cam: &mut Camera) {
+        cam.pos += right(cam.dir) * self.offset;
+    }
+}
+
+/// Twists and jolts the view along with `postprocess::Shake`
+pub struct CameraShake {
+    wobble: Vec2,
+}
+
+impl CameraShake {
+    pub fn new(shake: &Shake) -> Self {
+        Self {
+            wobble: shake.wobble(),
+        }
+    }
+}
+
+impl CameraModifier for CameraShake {
+    fn apply(&self, cam: &mut Camera) {
+        let twist = Vec2::from_angle(self.wobble.x * SHAKE_YAW);
+        cam.dir = twist.rotate(cam.dir);
+        cam.plane = twist.rotate(cam.plane);
+        cam.pitch += self.wobble.y * SHAKE_PITCH;
+    }
+}
+
+/// Camera effects that follow what the player is doing, updated along with the world
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct CameraEffects {
+    pub bob: HeadBob,
+    pub kick: FovKick,
+    pub lean: Lean,
+}
+
+impl CameraEffects {
+    /// Every effect, in the order they should be applied
+    pub fn modifiers(&self) -> [&dyn CameraModifier; 3] {
+        [&self.bob, &self.kick, &self.lean]
+    }
+
+    pub fn lerp(&self, to: &CameraEffects, alpha: f32) -> CameraEffects {
+        // The phase wraps around every turn, so go the way it was stepping
+        let phase_step = (to.bob.phase - self.bob.phase).rem_euclid(2. * PI);
+        CameraEffects {
+            bob: HeadBob {
+                phase: self.bob.phase + phase_step * alpha,
+                amount: lerp(self.bob.amount, to.bob.amount, alpha),
+            },
+            kick: FovKick {
+                amount: lerp(self.kick.amount, to.kick.amount, alpha),
+            },
+            lean: Lean {
+                offset: lerp(self.lean.offset, to.lean.offset, alpha),
+            },
+        }
+    }
+}
+
+/// Camera effects from the previous update, used to smooth out drawing between updates
+#[derive(Resource, Default, Debug)]
+pub struct PreviousCameraEffects(pub CameraEffects);
+
+pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
+    world.init_resource::<CameraEffects>();
+    world.init_resource::<PreviousCameraEffects>();
+
+    schedule.add_systems((head_bob, fov_kick, lean));
+}
+
+/// Direction to the right of a camera looking down `dir`
+fn right(dir: Vec2) -> Vec2 {
+    -dir.perp().normalize_or_zero()
+}
+
+/// Moves `value` towards `target`, `rate` times the distance between them per second
+fn ease(value: f32, target: f32, rate: f32, delta: f32) -> f32 {
+    lerp(value, target, (rate * delta).min(1.))
+}
+
+fn head_bob(
+    time: Res<Time>,
+    mut effects: ResMut<CameraEffects>,
+    query: Query<&components::Movement, With<components::Player>>,
+) {
+    for movement in query.iter() {
+        let speed = movement.velocity().length() * movement.speed();
+        let bob = &mut effects.bob;
+
+        let target = speed / components::MoveMode::Sprint.speed();
+        bob.amount = ease(bob.amount, target, BOB_EASE, time.delta());
+        bob.phase = (bob.phase + speed * BOB_RATE * PI * time.delta()) % (2. * PI);
+    }
+}
+
+fn fov_kick(
+    time: Res<Time>,
+    mut effects: ResMut<CameraEffects>,
+    query: Query<(&components::Player, &components::Movement)>,
+) {
+    for (player, movement) in query.iter() {
+        let sprinting =
+            player.mode == components::MoveMode::Sprint && movement.velocity() != Vec2::ZERO;
+        let kick = &mut effects.kick;
+        kick.amount = ease(kick.amount, sprinting as u8 as f32, FOV_EASE, time.delta());
+    }
+}
+
+fn lean(
+    time: Res<Time>,
+    map: Res<Map>,
+    cam: Res<Camera>,
+    mut effects: ResMut<CameraEffects>,
+    query: Query<&components::Player>,
+) {
+    for player in query.iter() {
+        let lean = &mut effects.lean;
+        let target = player.lean.clamp(-1., 1.) * LEAN_DISTANCE;
+        lean.offset = ease(lean.offset, target, LEAN_EASE, time.delta());
+        lean.offset = lean_room(&map, cam.pos, cam.dir, lean.offset);
+    }
+}
+
+/// Cuts a lean of `offset` short so it stops before any wall beside `pos`
+fn lean_room(map: &Map, pos: Vec2, dir: Vec2, offset: f32) -> f32 {
+    if offset == 0. {
+        return 0.;
+    }
+
+    let side = right(dir) * offset.signum();
+    let room = physics::raycast(map, pos, side, offset.abs() + LEAN_WALL_GAP)
+        .map_or(offset.abs(), |hit| (hit.dist - LEAN_WALL_GAP).max(0.));
+    offset.clamp(-room, room)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+
+    #[test]
+    fn effects_stack() {
+        let mut cam = Camera::default();
+        cam.fit_plane(0.66);
+        let effects = CameraEffects {
+            bob: HeadBob {
+                phase: PI / 2.,
+                amount: 1.,
+            },
+            kick: FovKick { amount: 1. },
+            lean: Lean { offset: 0.2 },
+        };
+
+        let mut moved = cam;
+        apply(&mut moved, &effects.modifiers());
+        assert!(moved.z > cam.z);
+        assert!(moved.plane.length() > cam.plane.length());
+        // Leaning right goes the same way as the right edge of the screen
+        assert!((moved.pos - cam.pos).dot(cam.plane) > 0.);
+        assert_eq!(moved.dir, cam.dir);
+
+        // Nothing moves while standing still
+        let mut still = cam;
+        apply(&mut still, &CameraEffects::default().modifiers());
+        assert_eq!(still.pos, cam.pos);
+        assert_eq!(still.plane, cam.plane);
+        assert_eq!(still.z, cam.z);
+    }
+
+    #[test]
+    fn effects_move_smoothly_between_updates() {
+        let from = CameraEffects {
+            bob: HeadBob {
+                phase: 2. * PI - 0.2,
+                amount: 0.,
+            },
+            ..Default::default()
+        };
+        let to = CameraEffects {
+            bob: HeadBob {
+                phase: 0.2,
+                amount: 1.,
+            },
+            kick: FovKick { amount: 1. },
+            lean: Lean { offset: -0.3 },
+        };
+
+        let half = from.lerp(&to, 0.5);
+        assert_eq!(half.bob.amount, 0.5);
+        assert_eq!(half.kick.amount, 0.5);
+        assert_eq!(half.lean.offset, -0.15);
+        // Keeps stepping forward across the wrap instead of running back through a whole turn
+        assert!(
+            (half.bob.phase - 2. * PI).abs() < 0.001,
+            "phase {}",
+            half.bob.phase
+        );
+        assert!((from.lerp(&to, 1.).bob.phase.sin() - to.bob.phase.sin()).abs() < 0.001);
+    }
+
+    #[test]
+    fn leaning_stops_at_walls() {
+        let mut map = Map::new(3, 3);
+        map.set_tile(2, 1, Tile::Wall);
+        let pos = vec2(1.7, 1.5);
+
+        // Looking down +y the wall at +x is on the right
+        let offset = lean_room(&map, pos, Vec2::Y, LEAN_DISTANCE);
+        let room = 0.3 - LEAN_WALL_GAP;
+        assert!((offset - room).abs() < 0.001, "leaned {offset}");
+        assert_eq!(
+            lean_room(&map, pos, Vec2::Y, -LEAN_DISTANCE),
+            -LEAN_DISTANCE
+        );
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{graphics::Color, prelude::*};
+use std::collections::HashSet;
+
+use crate::{
+    graphics::{Color, TextureId},
+    prelude::*,
+};
 use bevy_ecs::prelude::*;
 
 #[derive(Component, Clone, Copy)]
@@ -18,6 +23,20 @@ impl Default for Transform {
     }
 }
 
+/// Transform from the previous update, used to smooth out drawing between updates
+#[derive(Component, Clone, Copy, Default)]
+pub struct PreviousTransform(pub Transform);
+
+impl PreviousTransform {
+    pub fn lerp(&self, current: &Transform, alpha: f32) -> Transform {
+        Transform {
+            pos: self.0.pos.lerp(current.pos, alpha),
+            dir: self.0.dir.lerp(current.dir, alpha),
+            scale: self.0.scale.lerp(current.scale, alpha),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Movement {
     vel: Vec2,
@@ -47,44 +66,181 @@ impl Movement {
     pub fn set_velocity(&mut self, vel: Vec2) {
         self.vel = vel.normalize_or_zero();
     }
+
+    /// Cancels the part of the velocity heading into a surface with this normal,
+    /// keeping the rest so the entity slides along it
+    pub fn block(&mut self, normal: Vec2) {
+        let into = self.vel.dot(normal);
+        if into < 0. {
+            self.vel -= normal * into;
+        }
+    }
 }
 
+/// Axis aligned box centered on the entity's position
 #[derive(Component)]
 pub struct Collider {
     pub size: Vec2,
+    /// Solid colliders block other moving colliders instead of only reporting a hit
+    pub solid: bool,
 }
 
 impl Default for Collider {
     fn default() -> Self {
         Self {
             size: Vec2::splat(1.),
+            solid: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Shape {
+    /// Box centered on the position
+    Box(Vec2),
+    Circle(f32),
+}
+
+/// Area that reports moving colliders entering, staying in and leaving it
+#[derive(Component)]
+pub struct Trigger {
+    pub shape: Shape,
+    inside: HashSet<Entity>,
+}
+
+impl Trigger {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            inside: HashSet::new(),
         }
     }
+
+    pub fn contains(&self, ent: Entity) -> bool {
+        self.inside.contains(&ent)
+    }
+
+    pub(crate) fn inside_mut(&mut self) -> &mut HashSet<Entity> {
+        &mut self.inside
+    }
 }
 
 #[derive(Component, Default)]
-pub struct Interactable;
+pub struct Interactable {
+    /// What interacting does, shown to the player when they're close enough
+    pub prompt: &'static str,
+}
+
+/// Note that can be picked up and read, `id` indexes into `notes::Notes`
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Note {
+    pub id: usize,
+}
 
 #[derive(Component, Default)]
-pub struct Exit;
+pub struct Exit {
+    /// Set once the player has left through it, so leaving only counts once
+    pub taken: bool,
+}
+
+/// What a trigger placed from a room prefab does when the player walks into it
+#[derive(Clone, Debug, serde::Deserialize)]
+pub enum TriggerAction {
+    /// Plays a sound from `assets/sounds`, like a scripted scare
+    Sound(String),
+    /// Shows a message on the HUD
+    Message(String),
+    /// Shakes the screen by this much trauma
+    Shake(f32),
+}
+
+/// Trigger set up from level data, see `map::TriggerDef`
+#[derive(Component, Clone, Debug)]
+pub struct Scripted {
+    pub action: TriggerAction,
+    /// Removed after going off once instead of every time the player walks in
+    pub once: bool,
+}
 
 #[derive(Component, Default)]
 pub struct Player {
     pub batteries: u32,
+    pub mode: MoveMode,
+    /// Which way the player wants to lean, -1 for left and 1 for right
+    pub lean: f32,
+}
+
+/// Light the player can keep on, running off their batteries
+#[derive(Component, Default)]
+pub struct Flashlight {
+    pub on: bool,
+    /// Seconds left on the battery in use
+    pub charge: f32,
+    /// How bright it shines right now, from 0 to 1. Drops while it flickers
+    pub brightness: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MoveMode {
+    #[default]
+    Walk,
+    Sprint,
+    Crouch,
+}
+
+impl MoveMode {
+    pub fn speed(&self) -> f32 {
+        match self {
+            Self::Walk => 0.2,
+            Self::Sprint => 0.32,
+            Self::Crouch => 0.1,
+        }
+    }
+
+    /// Distance in tiles monsters can hear the player moving from
+    pub fn noise(&self) -> f32 {
+        match self {
+            Self::Walk => 4.,
+            Self::Sprint => 10.,
+            Self::Crouch => 1.5,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// Set once stamina runs out. Sprinting is not possible again until it partly recovers
+    pub exhausted: bool,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self {
+            current: 100.,
+            max: 100.,
+            exhausted: false,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct Monster {
     pub state: MonsterState,
-    pub attack_time: u32,
+    pub attack_time: f32, // Seconds left before the target is killed
 }
 pub enum MonsterState {
-    Rest(u32), // Duration to rest for in game ticks
+    Rest(f32), // Duration to rest for in seconds
     Wander,
     Attack(Entity), // Target
     Flee(Vec2),
 }
 
+/// Point monsters can crawl out of during the game
+#[derive(Component, Default)]
+pub struct Nest;
+
 #[derive(Component, Default)]
 pub struct MonsterTarget {
     pub is_dead: bool,
@@ -96,11 +252,31 @@ pub struct Navigator {
     pub path: Vec<Vec2>,
 }
 
+/// Rectangle of a texture in pixels
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Region {
+    pub pos: UVec2,
+    pub size: UVec2,
+}
+
 #[derive(Component, Default)]
 pub struct Sprite {
     pub height: f32,
     pub color: Color,
-    pub texture: String,
+    /// Nothing is drawn without a texture
+    pub texture: Option<TextureId>,
+    /// Part of the texture that gets drawn, all of it if `None`
+    pub region: Option<Region>,
+}
+
+/// Lights up the tiles around it, see `lighting::LightMap`
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Light {
+    /// Tiles the light reaches
+    pub radius: f32,
+    pub intensity: f32,
+    /// How far the light dims when it flickers, 0 for a steady light
+    pub flicker: f32,
 }
 
 #[derive(Component, Default)]
@@ -0,0 +1,329 @@
+use std::{fs::File, path::Path};
+
+use assets_manager::BoxedError;
+
+use crate::{
+    graphics::{Color, Texture},
+    prelude::*,
+};
+
+/// Layout of a glyph sheet, read from a `.ron` file next to it
+#[derive(serde::Deserialize)]
+struct FontDef {
+    /// Image with the glyphs, relative to the definition
+    texture: String,
+    cell: (u32, u32),
+    columns: u32,
+    first: u32,
+    missing: char,
+    spacing: u32,
+    space: u32,
+    line_height: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    /// Where the glyph's ink starts inside its cell
+    left: u32,
+    width: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Bitmap font drawn from a glyph sheet. Glyphs are masks, so text takes on any colour
+pub struct Font {
+    sheet: Texture,
+    cell: UVec2,
+    columns: u32,
+    first: u32,
+    missing: char,
+    spacing: u32,
+    line_height: u32,
+    glyphs: Vec<Glyph>,
+}
+
+impl Font {
+    /// Reads a font definition like `assets/font.ron` and the glyph sheet it points to
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BoxedError> {
+        let path = path.as_ref();
+        let def: FontDef = ron::de::from_reader(File::open(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let sheet = Texture::from(image::open(dir.join(&def.texture))?);
+        Ok(Self::new(sheet, def))
+    }
+
+    fn new(sheet: Texture, def: FontDef) -> Self {
+        let cell = uvec2(def.cell.0, def.cell.1);
+        let rows = sheet.height() / cell.y;
+
+        // Measure the ink in every cell so narrow letters don't leave gaps
+        let glyphs = (0..def.columns * rows)
+            .map(|i| {
+                let origin = uvec2(i % def.columns, i / def.columns) * cell;
+                let inked = |x: u32| {
+                    (0..cell.y).any(|y| {
+                        let idx = crate::idx((origin.x + x) * 4, (origin.y + y) * 4, sheet.width());
+                        sheet.pixel(idx).a > 0
+                    })
+                };
+
+                match (0..cell.x).find(|x| inked(*x)) {
+                    Some(left) => {
+                        let right = (0..cell.x).rev().find(|x| inked(*x)).unwrap_or(left);
+                        Glyph {
+                            left,
+                            width: right - left + 1,
+                        }
+                    }
+                    None => Glyph {
+                        left: 0,
+                        width: def.space,
+                    },
+                }
+            })
+            .collect();
+
+        Self {
+            sheet,
+            cell,
+            columns: def.columns,
+            first: def.first,
+            missing: def.missing,
+            spacing: def.spacing,
+            line_height: def.line_height,
+            glyphs,
+        }
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    /// Index of the glyph drawn for `c`, falling back to the missing glyph
+    fn glyph(&self, c: char) -> usize {
+        let index = |c: char| {
+            (c as u32)
+                .checked_sub(self.first)
+                .filter(|i| (*i as usize) < self.glyphs.len())
+        };
+        index(c).or_else(|| index(self.missing)).unwrap_or(0) as usize
+    }
+
+    /// Pixels a single line of text takes up
+    pub fn width(&self, line: &str) -> u32 {
+        let advance: u32 = line
+            .chars()
+            .map(|c| self.glyphs[self.glyph(c)].width + self.spacing)
+            .sum();
+        advance.saturating_sub(self.spacing)
+    }
+
+    /// Breaks text into lines no wider than `max_width`, at newlines and between words.
+    /// Words too long for a line get split wherever they run out of room
+    pub fn wrap(&self, text: &str, max_width: u32) -> Vec<String> {
+        let mut lines = Vec::new();
+        for paragraph in text.lines() {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let joined = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{line} {word}")
+                };
+                if self.width(&joined) <= max_width {
+                    line = joined;
+                    continue;
+                }
+
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                for c in word.chars() {
+                    line.push(c);
+                    if self.width(&line) > max_width && line.chars().count() > 1 {
+                        line.pop();
+                        lines.push(std::mem::replace(&mut line, c.to_string()));
+                    }
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Draws a line of text with its top left corner at `pos`, on a screen `screen_width` pixels wide.
+    /// Anything off screen is left out
+    pub fn draw(
+        &self,
+        screen: &mut [u8],
+        screen_width: usize,
+        pos: IVec2,
+        text: &str,
+        color: Color,
+    ) {
+        let screen_size = uvec2(
+            screen_width as u32,
+            (screen.len() / 4 / screen_width) as u32,
+        );
+        let clip = (IVec2::ZERO, screen_size.as_ivec2());
+        self.draw_clipped(screen, screen_width, pos, text, color, clip);
+    }
+
+    /// Wraps text to fit in the box at `pos` of `size` and draws it aligned inside.
+    /// Lines that don't fit are cut off at the bottom. Returns how tall the wrapped text is
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_box(
+        &self,
+        screen: &mut [u8],
+        screen_width: usize,
+        pos: IVec2,
+        size: UVec2,
+        text: &str,
+        color: Color,
+        align: Align,
+    ) -> u32 {
+        let screen_size = ivec2(
+            screen_width as i32,
+            (screen.len() / 4 / screen_width) as i32,
+        );
+        let clip = (
+            pos.max(IVec2::ZERO),
+            (pos + size.as_ivec2()).min(screen_size),
+        );
+
+        let lines = self.wrap(text, size.x);
+        for (i, line) in lines.iter().enumerate() {
+            let x = match align {
+                Align::Left => 0,
+                Align::Center => (size.x as i32 - self.width(line) as i32) / 2,
+                Align::Right => size.x as i32 - self.width(line) as i32,
+            };
+            let line_pos = pos + ivec2(x, (i as u32 * self.line_height) as i32);
+            self.draw_clipped(screen, screen_width, line_pos, line, color, clip);
+        }
+        lines.len() as u32 * self.line_height
+    }
+
+    /// Draws a line, only touching pixels from `clip.0` up to but not including `clip.1`
+    fn draw_clipped(
+        &self,
+        screen: &mut [u8],
+        screen_width: usize,
+        pos: IVec2,
+        text: &str,
+        color: Color,
+        clip: (IVec2, IVec2),
+    ) {
+        let mut x = pos.x;
+        for c in text.chars() {
+            let index = self.glyph(c);
+            let glyph = self.glyphs[index];
+            let origin =
+                uvec2(index as u32 % self.columns, index as u32 / self.columns) * self.cell;
+
+            for gy in 0..self.cell.y {
+                let sy = pos.y + gy as i32;
+                if sy < clip.0.y || sy >= clip.1.y {
+                    continue;
+                }
+                for gx in 0..glyph.width {
+                    let sx = x + gx as i32;
+                    if sx < clip.0.x || sx >= clip.1.x {
+                        continue;
+                    }
+
+                    let idx = crate::idx(
+                        (origin.x + glyph.left + gx) * 4,
+                        (origin.y + gy) * 4,
+                        self.sheet.width(),
+                    );
+                    let ink = self.sheet.pixel(idx).a;
+                    if ink == 0 {
+                        continue;
+                    }
+
+                    let i = (sx as usize + sy as usize * screen_width) * 4;
+                    let alpha = (ink as u32 * color.a as u32 / 255) as u8;
+                    let mut pixel = Color::from(&screen[i..i + 4]);
+                    pixel.blend(Color { a: alpha, ..color });
+                    screen[i..i + 4].copy_from_slice(&pixel.slice());
+                }
+            }
+
+            x += (glyph.width + self.spacing) as i32;
+            if x >= clip.1.x {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::load(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/font.ron")).unwrap()
+    }
+
+    #[test]
+    fn glyphs_have_their_own_width() {
+        let font = font();
+        assert!(font.width("i") < font.width("W"));
+        assert_eq!(font.width(""), 0);
+        assert_eq!(
+            font.width("ab"),
+            font.width("a") + font.spacing + font.width("b")
+        );
+        // Not on the sheet, drawn as the missing glyph
+        assert_eq!(font.width("\u{1F47B}"), font.width("?"));
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        let font = font();
+        let max = font.width("the generator");
+        let lines = font.wrap("the generator hums\nquietly", max);
+        assert_eq!(lines, ["the generator", "hums", "quietly"]);
+
+        for line in font.wrap("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", max) {
+            assert!(font.width(&line) <= max, "{line} is too wide");
+        }
+    }
+
+    #[test]
+    fn clips_at_screen_edges() {
+        let font = font();
+        let (width, height) = (32, 16);
+        let mut screen = vec![0; width * height * 4];
+        let white = Color::from_rgb(255, 255, 255);
+
+        font.draw(&mut screen, width, ivec2(24, 10), "WWWW", white);
+        font.draw(&mut screen, width, ivec2(-8, -4), "WWWW", white);
+        assert!(screen.iter().any(|val| *val > 0));
+
+        // Nothing spills outside the box
+        let mut screen = vec![0; width * height * 4];
+        font.draw_box(
+            &mut screen,
+            width,
+            ivec2(4, 2),
+            uvec2(8, 8),
+            "WWWW WWWW",
+            white,
+            Align::Center,
+        );
+        for (i, pixel) in screen.chunks_exact(4).enumerate() {
+            let (x, y) = (i % width, i / width);
+            if !(4..12).contains(&x) || !(2..10).contains(&y) {
+                assert_eq!(pixel, [0; 4], "pixel at {x}, {y} was drawn outside the box");
+            }
+        }
+    }
+}
@@ -1,12 +1,14 @@
+use std::collections::HashMap;
+
 use assets_manager::{
     loader::{ImageLoader, LoadFrom},
-    Asset,
+    Asset, AssetCache,
 };
+use bevy_ecs::system::Resource;
 use image::DynamicImage;
+use log::warn;
 
-use crate::WIDTH;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -61,18 +63,25 @@ impl From<&[u8]> for Color {
     }
 }
 
+/// RGBA8 image, no matter what format it was loaded from
+#[derive(Clone)]
 pub struct Texture {
     width: u32,
     height: u32,
     bytes: Vec<u8>,
+    /// Each half the size of the one before, see `Texture::generate_mipmaps`
+    mips: Vec<Texture>,
 }
 
 impl From<DynamicImage> for Texture {
     fn from(value: DynamicImage) -> Self {
+        // Grayscale, RGB and 16 bit images all get converted so pixels can be read the same way
+        let img = value.into_rgba8();
         Texture {
-            width: value.width(),
-            height: value.height(),
-            bytes: value.as_bytes().into(),
+            width: img.width(),
+            height: img.height(),
+            bytes: img.into_raw(),
+            mips: Vec::new(),
         }
     }
 }
@@ -99,67 +108,331 @@ impl Texture {
             a: self.bytes[idx + 3],
         }
     }
+
+    /// Pixel at texture coordinates from 0 to 1, wrapping around outside of that
+    pub fn sample(&self, uv: crate::Vec2) -> Color {
+        let x = ((uv.x * self.width as f32).floor() as i32).rem_euclid(self.width as i32) as u32;
+        let y = ((uv.y * self.height as f32).floor() as i32).rem_euclid(self.height as i32) as u32;
+        self.pixel(crate::idx(x * 4, y * 4, self.width))
+    }
+
+    /// Builds smaller and smaller box filtered copies of the texture, down to a single pixel
+    pub fn generate_mipmaps(&mut self) {
+        self.mips.clear();
+
+        let (mut width, mut height) = (self.width, self.height);
+        let mut bytes = self.bytes.clone();
+        while width > 1 || height > 1 {
+            let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+            let mut next = Vec::with_capacity((next_width * next_height * 4) as usize);
+
+            for y in 0..next_height {
+                for x in 0..next_width {
+                    // Average the 2x2 block, repeating the edge for odd sizes
+                    let block = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                        let x = (x * 2 + dx).min(width - 1);
+                        let y = (y * 2 + dy).min(height - 1);
+                        crate::idx(x * 4, y * 4, width)
+                    });
+                    for channel in 0..4 {
+                        let sum: u32 = block.iter().map(|i| bytes[i + channel] as u32).sum();
+                        next.push((sum / 4) as u8);
+                    }
+                }
+            }
+
+            self.mips.push(Texture {
+                width: next_width,
+                height: next_height,
+                bytes: next.clone(),
+                mips: Vec::new(),
+            });
+            (width, height, bytes) = (next_width, next_height, next);
+        }
+    }
+
+    /// Mipmap to draw with when every screen pixel covers `texels_per_pixel` texels.
+    /// Falls back to the full texture without mipmaps
+    pub fn mip(&self, texels_per_pixel: f32) -> &Texture {
+        let level = texels_per_pixel.log2().floor().max(0.) as usize;
+        if level == 0 {
+            return self;
+        }
+        self.mips
+            .get(level - 1)
+            .or(self.mips.last())
+            .unwrap_or(self)
+    }
+}
+
+/// Handle to a texture in the `TextureRegistry`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureId(usize);
+
+/// Resolves texture names to handles once, so drawing doesn't have to look textures up by path.
+/// Names can be registered before their textures are loaded, see `TextureRegistry::load`
+#[derive(Resource, Default)]
+pub struct TextureRegistry {
+    ids: HashMap<String, TextureId>,
+    names: Vec<String>,
+    textures: Vec<Option<Texture>>,
+    /// How many of the registered names have been through `load`
+    loaded: usize,
 }
 
-pub fn draw_text(screen: &mut [u8], pos: crate::UVec2, text: &str) {
-    let img = image::open("assets/font.png").expect("missing assets/font.png");
+impl TextureRegistry {
+    /// Handle for the texture at `textures/<name>`, registering it if it's new
+    pub fn id(&mut self, name: &str) -> TextureId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = TextureId(self.names.len());
+        self.ids.insert(name.into(), id);
+        self.names.push(name.into());
+        self.textures.push(None);
+        id
+    }
+
+    /// Handle of a texture that was already registered
+    pub fn find(&self, name: &str) -> Option<TextureId> {
+        self.ids.get(name).copied()
+    }
+
+    /// The texture behind a handle, if it could be loaded
+    pub fn get(&self, id: TextureId) -> Option<&Texture> {
+        self.textures.get(id.0)?.as_ref()
+    }
 
-    let mut pos = pos;
-    for c in text.chars() {
-        pos.x += 10;
-        blit_sheet(screen, pos, &img, c as usize, 16, 16);
+    /// Registers a texture that doesn't come from the asset folder
+    pub fn insert(&mut self, name: &str, mut texture: Texture) -> TextureId {
+        texture.generate_mipmaps();
+        let id = self.id(name);
+        self.textures[id.0] = Some(texture);
+        id
     }
+
+    /// Loads every texture registered since the last call
+    pub fn load(&mut self, assets: &AssetCache) {
+        for i in self.loaded..self.names.len() {
+            if self.textures[i].is_some() {
+                continue;
+            }
+
+            let name = &self.names[i];
+            match assets.load::<Texture>(&format!("textures.{name}")) {
+                Ok(handle) => {
+                    let mut texture = handle.cloned();
+                    texture.generate_mipmaps();
+                    self.textures[i] = Some(texture);
+                }
+                Err(err) => warn!("Could not load texture {name}: {err}"),
+            }
+        }
+        self.loaded = self.names.len();
+    }
+}
+
+/// Colour-grading lookup table, remapping every colour of a frame to another
+pub struct ColorLut {
+    size: usize,
+    table: Vec<[u8; 3]>,
 }
 
-fn blit_sheet(
+impl ColorLut {
+    /// LUT that leaves colours as they are
+    pub fn identity(size: usize) -> Self {
+        let scale = |val: usize| (val * 255 / (size - 1)) as u8;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push([scale(r), scale(g), scale(b)]);
+                }
+            }
+        }
+        Self { size, table }
+    }
+
+    /// Reads a LUT laid out as `size` squares of `size` by `size` pixels side by side.
+    /// Red goes along each square's x axis, green along y and blue from square to square
+    pub fn from_texture(tex: &Texture) -> Option<Self> {
+        let size = tex.height() as usize;
+        if size < 2 || tex.width() as usize != size * size {
+            return None;
+        }
+
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let idx = crate::idx((r + b * size) as u32 * 4, g as u32 * 4, tex.width());
+                    let color = tex.pixel(idx);
+                    table.push([color.r, color.g, color.b]);
+                }
+            }
+        }
+        Some(Self { size, table })
+    }
+
+    /// Remaps every pixel of an RGBA frame, blending between the closest entries
+    pub fn apply(&self, frame: &mut [u8]) {
+        for pixel in frame.chunks_exact_mut(4) {
+            let color = self.sample([pixel[0], pixel[1], pixel[2]]);
+            pixel[..3].copy_from_slice(&color);
+        }
+    }
+
+    fn sample(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let max = (self.size - 1) as f32;
+        let pos = rgb.map(|val| val as f32 / 255. * max);
+        let lo = pos.map(|val| (val as usize).min(self.size - 2));
+        let t = [0, 1, 2].map(|i| pos[i] - lo[i] as f32);
+
+        let entry = |r: usize, g: usize, b: usize| {
+            self.table[r + g * self.size + b * self.size * self.size].map(|val| val as f32)
+        };
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+
+        // Trilinear blend of the 8 entries around the colour
+        let [r, g, b] = lo;
+        let c00 = lerp(entry(r, g, b), entry(r + 1, g, b), t[0]);
+        let c10 = lerp(entry(r, g + 1, b), entry(r + 1, g + 1, b), t[0]);
+        let c01 = lerp(entry(r, g, b + 1), entry(r + 1, g, b + 1), t[0]);
+        let c11 = lerp(entry(r, g + 1, b + 1), entry(r + 1, g + 1, b + 1), t[0]);
+        let c0 = lerp(c00, c10, t[1]);
+        let c1 = lerp(c01, c11, t[1]);
+
+        lerp(c0, c1, t[2]).map(|val| val.round() as u8)
+    }
+}
+
+/// Fills a rectangle on a screen `width` pixels wide, clipping anything outside of it
+pub fn fill_rect(
     screen: &mut [u8],
-    dest: crate::UVec2,
-    sprite: &DynamicImage,
-    index: usize,
-    rows: usize,
-    columns: usize,
+    width: usize,
+    pos: crate::UVec2,
+    size: crate::UVec2,
+    color: Color,
 ) {
-    let pixels = sprite.as_bytes();
-    let width = sprite.width() as usize / rows * 4;
-    let height = sprite.height() as usize / columns;
-
-    // convert index to x and y coords
-    let idx_x = index % rows;
-    let idx_y = index / rows;
-
-    for y in 0..height {
-        // coordinates on the sprite sheet
-        let x_pos = idx_x * width;
-        let y_pos = idx_y * height * 4;
-
-        let i = dest.x as usize * 4 + dest.y as usize * WIDTH * 4 + y * WIDTH * 4;
-        let s = ((y as f32 * 4. + y_pos as f32) * width as f32 * (rows as f32 / 4.) + x_pos as f32)
-            as usize;
-
-        // blend pixels with prev screen frame
-        let pixels = &pixels[s..s + width];
-        let colors: Vec<Color> = pixels.chunks(4).map(Color::from).collect();
-
-        let screen_pixels = &screen[i..i + width];
-        let mut screen_colors: Vec<Color> = screen_pixels.chunks(4).map(Color::from).collect();
-
-        let mut vec = Vec::with_capacity(screen_pixels.len());
-        screen_colors
-            .iter_mut()
-            .enumerate()
-            .for_each(|(idx, color)| {
-                color.blend(colors[idx]);
-                let pix = color.slice();
-
-                for i in pix {
-                    vec.push(i);
-                }
-            });
+    let height = screen.len() / 4 / width;
+    let end_x = (pos.x + size.x).min(width as u32);
+    let end_y = (pos.y + size.y).min(height as u32);
+
+    for y in pos.y..end_y {
+        for x in pos.x..end_x {
+            let i = x as usize * 4 + y as usize * width * 4;
 
-        screen[i..i + width].copy_from_slice(&vec);
+            let mut pixel = Color::from(&screen[i..i + 4]);
+            pixel.blend(color);
+            screen[i..i + 4].copy_from_slice(&pixel.slice());
+        }
     }
 }
 
-fn blit(screen: &mut [u8], dest: crate::UVec2, sprite: &DynamicImage) {
-    blit_sheet(screen, dest, sprite, 0, 1, 1);
+/// Draws a texture scaled to `size` on a screen `width` pixels wide, clipping anything outside of it
+pub fn draw_texture(
+    screen: &mut [u8],
+    width: usize,
+    pos: crate::IVec2,
+    size: crate::UVec2,
+    texture: &Texture,
+) {
+    let height = (screen.len() / 4 / width) as i32;
+    let end = (pos + size.as_ivec2()).min(crate::ivec2(width as i32, height));
+
+    for y in pos.y.max(0)..end.y {
+        for x in pos.x.max(0)..end.x {
+            let tx = (x - pos.x) as u32 * texture.width / size.x;
+            let ty = (y - pos.y) as u32 * texture.height / size.y;
+            let i = x as usize * 4 + y as usize * width * 4;
+
+            let mut pixel = Color::from(&screen[i..i + 4]);
+            pixel.blend(texture.pixel(crate::idx(tx * 4, ty * 4, texture.width)));
+            screen[i..i + 4].copy_from_slice(&pixel.slice());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_lut_keeps_colours() {
+        let lut = ColorLut::identity(16);
+        let mut frame = vec![0, 0, 0, 255, 255, 255, 255, 255, 12, 200, 97, 128];
+        let original = frame.clone();
+
+        lut.apply(&mut frame);
+        assert_eq!(frame, original);
+    }
+
+    #[test]
+    fn reads_lut_from_texture() {
+        // 2x2x2 LUT that swaps red and blue
+        let size = 2;
+        let mut bytes = vec![0; size * size * size * 4];
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let i = ((r + b * size) + g * size * size) * 4;
+                    bytes[i..i + 4].copy_from_slice(&[
+                        b as u8 * 255,
+                        g as u8 * 255,
+                        r as u8 * 255,
+                        255,
+                    ]);
+                }
+            }
+        }
+        let tex = Texture {
+            width: (size * size) as u32,
+            height: size as u32,
+            bytes,
+            mips: Vec::new(),
+        };
+
+        let lut = ColorLut::from_texture(&tex).unwrap();
+        let mut frame = vec![255, 128, 0, 255];
+        lut.apply(&mut frame);
+        assert_eq!(frame, vec![0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn converts_any_format_to_rgba() {
+        let gray = image::GrayImage::from_raw(2, 1, vec![10, 200]).unwrap();
+        let tex = Texture::from(DynamicImage::ImageLuma8(gray));
+        assert_eq!(tex.bytes, vec![10, 10, 10, 255, 200, 200, 200, 255]);
+
+        let rgb16 = image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(1, 1, vec![65535, 0, 32896])
+            .unwrap();
+        let tex = Texture::from(DynamicImage::ImageRgb16(rgb16));
+        assert_eq!(tex.bytes, vec![255, 0, 128, 255]);
+    }
+
+    #[test]
+    fn wraps_non_power_of_two_sizes() {
+        let gray = image::GrayImage::from_raw(3, 1, vec![0, 100, 200]).unwrap();
+        let tex = Texture::from(DynamicImage::ImageLuma8(gray));
+
+        assert_eq!(tex.sample(crate::vec2(0.5, 0.)).r, 100);
+        assert_eq!(tex.sample(crate::vec2(1.9, 0.)).r, 200);
+        assert_eq!(tex.sample(crate::vec2(-0.1, 0.)).r, 200);
+    }
+
+    #[test]
+    fn mipmaps_shrink_to_a_pixel() {
+        let gray = image::GrayImage::from_raw(5, 3, vec![100; 15]).unwrap();
+        let mut tex = Texture::from(DynamicImage::ImageLuma8(gray));
+        tex.generate_mipmaps();
+
+        let sizes: Vec<(u32, u32)> = tex.mips.iter().map(|mip| (mip.width, mip.height)).collect();
+        assert_eq!(sizes, vec![(2, 1), (1, 1)]);
+        assert_eq!(tex.mips[1].bytes, vec![100, 100, 100, 255]);
+
+        assert_eq!(tex.mip(1.).width, 5);
+        assert_eq!(tex.mip(2.).width, 2);
+        assert_eq!(tex.mip(64.).width, 1);
+    }
 }
@@ -0,0 +1,258 @@
+use bevy_ecs::prelude::*;
+
+use crate::{
+    font::Font,
+    graphics::{self, Color},
+    player,
+    prelude::*,
+    state::game::{add_event, CoreSet, GameData},
+};
+
+/// Seconds a toast stays on screen, fading out over the last `TOAST_FADE` of them
+const TOAST_TIME: f32 = 2.5;
+const TOAST_FADE: f32 = 0.5;
+/// Most toasts shown at once, older ones get dropped
+const MAX_TOASTS: usize = 3;
+/// Gap between the HUD and the edges of the screen
+const MARGIN: i32 = 4;
+
+const TEXT_COLOR: Color = Color {
+    r: 220,
+    g: 220,
+    b: 210,
+    a: 230,
+};
+const DIM_COLOR: Color = Color {
+    r: 150,
+    g: 150,
+    b: 140,
+    a: 200,
+};
+
+/// Shows a short message near the top of the screen, like when something gets picked up
+pub struct Toast(pub String);
+
+/// Everything the HUD shows that isn't read straight from the world when drawing
+#[derive(Resource, Default)]
+pub struct Hud {
+    /// What pressing interact would do right now
+    prompt: Option<&'static str>,
+    /// Messages with how many seconds they have left
+    toasts: Vec<(String, f32)>,
+}
+
+pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
+    world.init_resource::<Hud>();
+    add_event::<Toast>(world, schedule);
+
+    schedule.add_systems((
+        update_prompt.in_base_set(CoreSet::Last),
+        update_toasts.in_base_set(CoreSet::Last),
+    ));
+}
+
+fn update_prompt(
+    mut hud: ResMut<Hud>,
+    player_query: Query<&components::Transform, With<components::Player>>,
+    interactable_query: Query<(
+        &components::Transform,
+        &components::Interactable,
+        Option<&components::Generator>,
+    )>,
+) {
+    hud.prompt = player_query.iter().find_map(|player_trans| {
+        interactable_query
+            .iter()
+            .filter(|(_, _, gen)| !gen.is_some_and(|gen| gen.is_on))
+            .map(|(trans, int, _)| (player_trans.pos.distance_squared(trans.pos), int.prompt))
+            .filter(|(dist, _)| *dist < player::INTERACT_RANGE)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, prompt)| prompt)
+    });
+}
+
+fn update_toasts(time: Res<Time>, mut hud: ResMut<Hud>, mut toast_reader: EventReader<Toast>) {
+    for (_, time_left) in hud.toasts.iter_mut() {
+        *time_left -= time.delta();
+    }
+    hud.toasts.retain(|(_, time_left)| *time_left > 0.);
+
+    for Toast(text) in toast_reader.iter() {
+        hud.toasts.push((text.clone(), TOAST_TIME));
+    }
+    let extra = hud.toasts.len().saturating_sub(MAX_TOASTS);
+    hud.toasts.drain(..extra);
+}
+
+/// What the HUD needs from the world, gathered by the state drawing it
+pub struct HudInfo<'a> {
+    pub hud: &'a Hud,
+    pub data: &'a GameData,
+    pub batteries: u32,
+    pub stamina: Option<&'a components::Stamina>,
+}
+
+/// Draws the HUD on top of a finished frame `width` pixels wide
+pub fn draw(screen: &mut [u8], width: usize, font: &Font, info: &HudInfo) {
+    let height = (screen.len() / 4 / width) as i32;
+    let size = ivec2(width as i32, height);
+    let line = font.line_height() as i32;
+
+    let batteries = format!("Batteries: {}", info.batteries);
+    text(
+        screen,
+        width,
+        font,
+        ivec2(MARGIN, MARGIN),
+        &batteries,
+        TEXT_COLOR,
+    );
+
+    let objective = match info.data.generators_required {
+        0 => "Power is on. Find the exit".to_string(),
+        1 => "1 generator left".to_string(),
+        left => format!("{left} generators left"),
+    };
+    let x = size.x - MARGIN - font.width(&objective) as i32;
+    text(
+        screen,
+        width,
+        font,
+        ivec2(x, MARGIN),
+        &objective,
+        TEXT_COLOR,
+    );
+
+    // Newest toast at the bottom of the stack, fading out before it goes
+    for (i, (toast, time_left)) in info.hud.toasts.iter().enumerate() {
+        let fade = (time_left / TOAST_FADE).min(1.);
+        let color = Color {
+            a: (TEXT_COLOR.a as f32 * fade) as u8,
+            ..TEXT_COLOR
+        };
+        let x = (size.x - font.width(toast) as i32) / 2;
+        let y = MARGIN + line * (i as i32 + 1);
+        text(screen, width, font, ivec2(x, y), toast, color);
+    }
+
+    if let Some(prompt) = info.hud.prompt {
+        let prompt = format!("E: {prompt}");
+        let x = (size.x - font.width(&prompt) as i32) / 2;
+        text(
+            screen,
+            width,
+            font,
+            ivec2(x, size.y * 2 / 3),
+            &prompt,
+            DIM_COLOR,
+        );
+    }
+
+    // stamina bar, hidden while full
+    if let Some(stamina) = info.stamina.filter(|stamina| stamina.current < stamina.max) {
+        let bar = uvec2(size.x as u32 / 4, 3);
+        let pos = uvec2(
+            (size.x as u32 - bar.x) / 2,
+            (size.y as u32).saturating_sub(bar.y + 6),
+        );
+        let fill = (bar.x as f32 * stamina.current / stamina.max) as u32;
+        let color = if stamina.exhausted {
+            Color::from_rgba(160, 24, 24, 200)
+        } else {
+            Color::from_rgba(200, 200, 200, 160)
+        };
+
+        graphics::fill_rect(screen, width, pos, bar, Color::from_rgba(0, 0, 0, 120));
+        graphics::fill_rect(screen, width, pos, uvec2(fill, bar.y), color);
+    }
+}
+
+/// Draws text with a drop shadow so it stays readable on bright walls
+fn text(screen: &mut [u8], width: usize, font: &Font, pos: IVec2, text: &str, color: Color) {
+    let shadow = Color::from_rgba(0, 0, 0, (color.a as u32 * 2 / 3) as u8);
+    font.draw(screen, width, pos + 1, text, shadow);
+    font.draw(screen, width, pos, text, color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether anything was drawn in the part of the rectangle that's on screen
+    fn drawn(screen: &[u8], width: usize, pos: IVec2, size: IVec2) -> bool {
+        let height = (screen.len() / 4 / width) as i32;
+        let start = pos.max(IVec2::ZERO);
+        let end = (pos + size).min(ivec2(width as i32, height));
+        (start.y..end.y).any(|y| {
+            (start.x..end.x).any(|x| {
+                let i = (y as usize * width + x as usize) * 4;
+                screen[i..i + 4] != [0; 4]
+            })
+        })
+    }
+
+    #[test]
+    fn fits_on_tiny_screens() {
+        let font = Font::load(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/font.ron")).unwrap();
+        let line = font.line_height() as i32;
+        let hud = Hud {
+            prompt: Some("start generator"),
+            toasts: vec![("Picked up a battery".to_string(), 0.2)],
+        };
+        let info = HudInfo {
+            hud: &hud,
+            data: &GameData::default(),
+            batteries: 12,
+            stamina: Some(&components::Stamina {
+                current: 1.,
+                ..Default::default()
+            }),
+        };
+        let batteries = font.width("Batteries: 12") as i32;
+        let prompt = font.width("E: start generator") as i32;
+
+        let (width, height) = (384, 216);
+        let mut screen = vec![0; width * height * 4];
+        draw(&mut screen, width, &font, &info);
+        let (w, h) = (width as i32, height as i32);
+        assert!(drawn(
+            &screen,
+            width,
+            IVec2::splat(MARGIN),
+            ivec2(batteries, line)
+        ));
+        assert!(drawn(
+            &screen,
+            width,
+            ivec2((w - prompt) / 2, h * 2 / 3),
+            ivec2(prompt, line)
+        ));
+        // Nothing reaches the sides, so no text ran off one row and wrapped onto the next
+        assert!(!drawn(&screen, width, IVec2::ZERO, ivec2(1, h)));
+        assert!(!drawn(&screen, width, ivec2(w - 1, 0), ivec2(1, h)));
+
+        // Cut off at the edges, but what fits is still there
+        let (width, height) = (24, 12);
+        let mut screen = vec![0; width * height * 4];
+        draw(&mut screen, width, &font, &info);
+        let (w, h) = (width as i32, height as i32);
+        assert!(drawn(
+            &screen,
+            width,
+            IVec2::splat(MARGIN),
+            ivec2(batteries, line)
+        ));
+        assert!(drawn(
+            &screen,
+            width,
+            ivec2((w - prompt) / 2, h * 2 / 3),
+            ivec2(prompt, line)
+        ));
+        // Above the battery count is left alone
+        assert!(!drawn(&screen, width, IVec2::ZERO, ivec2(w, MARGIN)));
+
+        // Too small for anything, but still mustn't write outside the frame
+        let mut screen = vec![0; 4];
+        draw(&mut screen, 1, &font, &info);
+    }
+}
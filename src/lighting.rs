@@ -0,0 +1,263 @@
+use bevy_ecs::prelude::*;
+
+use crate::{
+    components::Light,
+    graphics::Color,
+    map::{Map, Tile},
+    postprocess::PostProcess,
+    prelude::*,
+    state::game::CoreSet,
+};
+
+/// What things fade into with distance
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct Fog {
+    pub color: Color,
+    /// Exponent applied to how visible things are. Above 1 fog closes in faster, below 1 slower
+    pub curve: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: Color::from_rgb(0, 0, 0),
+            curve: 1.,
+        }
+    }
+}
+
+/// Look of a level, set in `assets/rooms.ron`
+#[derive(Resource, Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct Atmosphere {
+    /// How quickly things fade into the fog with distance
+    pub darkness: f32,
+    pub fog: Fog,
+    /// Name of the colour-grading LUT texture applied to every frame, if any
+    pub grade: Option<String>,
+    pub post: PostProcess,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            darkness: 3.5,
+            fog: Fog::default(),
+            grade: None,
+            post: PostProcess::default(),
+        }
+    }
+}
+
+/// How much light reaches every tile of the map, recomputed each update from the `Light`s in the world.
+/// Added on top of the global light intensity when drawing
+#[derive(Resource, Default)]
+pub struct LightMap {
+    width: u32,
+    height: u32,
+    levels: Vec<f32>,
+}
+
+impl LightMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            levels: vec![0.; (width * height) as usize],
+        }
+    }
+
+    /// Light level of the tile `pos` is on. Nothing outside the map is lit
+    pub fn level(&self, pos: Vec2) -> f32 {
+        if pos.x < 0. || pos.y < 0. {
+            return 0.;
+        }
+
+        let tile = pos.as_uvec2();
+        if tile.x >= self.width || tile.y >= self.height {
+            return 0.;
+        }
+        self.levels[crate::idx(tile.x, tile.y, self.width)]
+    }
+
+    pub fn clear(&mut self) {
+        self.levels.iter_mut().for_each(|level| *level = 0.);
+    }
+
+    /// Lights up every empty tile in range that can see `pos`, fading out towards the light's radius
+    pub fn add(&mut self, map: &Map, pos: Vec2, light: &Light, brightness: f32) {
+        let min = (pos - light.radius).floor().max(Vec2::ZERO).as_uvec2();
+        let max = (pos + light.radius)
+            .ceil()
+            .min(vec2(self.width as f32, self.height as f32))
+            .as_uvec2();
+
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                if map.get_tile(x, y) != Some(&Tile::Empty) {
+                    continue;
+                }
+
+                let center = uvec2(x, y).as_vec2() + 0.5;
+                let to_tile = center - pos;
+                let dist = to_tile.length();
+                if dist >= light.radius {
+                    continue;
+                }
+
+                // Anything solid in between casts a shadow
+                if dist > 0.01 && physics::raycast(map, pos, to_tile, 1.).is_some() {
+                    continue;
+                }
+
+                let falloff = 1. - dist / light.radius;
+                self.levels[crate::idx(x, y, self.width)] += brightness * falloff * falloff;
+            }
+        }
+    }
+}
+
+/// Cone of light, like a flashlight. Doesn't get blocked by walls,
+/// so it should only come from where the camera is
+#[derive(Clone, Copy, Debug)]
+pub struct Spotlight {
+    pub pos: Vec2,
+    pub dir: Vec2,
+    pub range: f32,
+    /// Half the width of the cone in radians
+    pub angle: f32,
+    pub intensity: f32,
+}
+
+impl Spotlight {
+    /// How much light reaches `pos`, fading towards the range and the edges of the cone
+    pub fn level(&self, pos: Vec2) -> f32 {
+        let to_pos = pos - self.pos;
+        let dist = to_pos.length();
+        if dist >= self.range {
+            return 0.;
+        }
+        if dist < 0.001 {
+            return self.intensity;
+        }
+
+        let angle = self.dir.angle_between(to_pos).abs();
+        if angle >= self.angle {
+            return 0.;
+        }
+
+        // Soften the outer third of the cone
+        let edge = ((1. - angle / self.angle) * 3.).min(1.);
+        let falloff = 1. - dist / self.range;
+        self.intensity * falloff * edge
+    }
+}
+
+/// How bright a light flickering by `amount` is at some point in time, from 0 to 1.
+/// Lights are offset by a seed, like their position, so they don't all flicker in sync
+pub fn flicker(amount: f32, seed: Vec2, time: f32) -> f32 {
+    if amount <= 0. {
+        return 1.;
+    }
+
+    let seed = seed.x * 12.9898 + seed.y * 78.233;
+    let wave = (time * 2.3 + seed).sin() * (time * 7.9 + seed * 3.).sin() * (time * 17.1).sin();
+    // Mostly on with the odd short dip
+    let dip = ((wave - 0.2) * 4.).clamp(0., 1.);
+    1. - amount * dip
+}
+
+pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
+    world.init_resource::<LightMap>();
+    schedule.add_system(update_lightmap.in_base_set(CoreSet::Last));
+}
+
+fn update_lightmap(
+    map: Res<Map>,
+    time: Res<Time>,
+    mut lightmap: ResMut<LightMap>,
+    query: Query<(&components::Transform, &Light)>,
+) {
+    if lightmap.width != map.width() || lightmap.height != map.height() {
+        *lightmap = LightMap::new(map.width(), map.height());
+    }
+
+    lightmap.clear();
+    for (trans, light) in query.iter() {
+        let brightness = light.intensity * flicker(light.flicker, trans.pos, time.elapsed());
+        lightmap.add(&map, trans.pos, light, brightness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAMP: Light = Light {
+        radius: 4.,
+        intensity: 1.,
+        flicker: 0.,
+    };
+
+    fn map() -> Map {
+        let mut map = Map::new(7, 3);
+        map.set_tile(3, 0, Tile::Wall);
+        map.set_tile(3, 1, Tile::Wall);
+        map
+    }
+
+    #[test]
+    fn fades_with_distance() {
+        let map = map();
+        let mut lightmap = LightMap::new(map.width(), map.height());
+        lightmap.add(&map, vec2(1.5, 1.5), &LAMP, 1.);
+
+        let near = lightmap.level(vec2(1.5, 2.5));
+        let far = lightmap.level(vec2(0.5, 0.5));
+        assert!(
+            near > far && far > 0.,
+            "{near} should be brighter than {far}"
+        );
+        assert_eq!(lightmap.level(vec2(-1., 1.)), 0.);
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let map = map();
+        let mut lightmap = LightMap::new(map.width(), map.height());
+        lightmap.add(&map, vec2(1.5, 0.5), &LAMP, 1.);
+
+        assert_eq!(lightmap.level(vec2(4.5, 0.5)), 0.);
+        assert_eq!(
+            lightmap.level(vec2(3.5, 1.5)),
+            0.,
+            "walls themselves aren't lit"
+        );
+        assert!(lightmap.level(vec2(3.5, 2.5)) > 0.);
+    }
+
+    #[test]
+    fn steady_lights_never_flicker() {
+        for i in 0..100 {
+            assert_eq!(flicker(LAMP.flicker, Vec2::ONE, i as f32 * 0.1), 1.);
+        }
+    }
+
+    #[test]
+    fn spotlight_only_lights_its_cone() {
+        let spot = Spotlight {
+            pos: Vec2::ZERO,
+            dir: Vec2::X,
+            range: 8.,
+            angle: 0.4,
+            intensity: 2.,
+        };
+
+        assert!(spot.level(vec2(2., 0.)) > spot.level(vec2(6., 0.)));
+        assert!(spot.level(vec2(2., 0.)) > spot.level(vec2(2., 0.6)));
+        assert_eq!(spot.level(vec2(2., 2.)), 0.);
+        assert_eq!(spot.level(vec2(-2., 0.)), 0.);
+        assert_eq!(spot.level(vec2(9., 0.)), 0.);
+    }
+}
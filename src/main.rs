@@ -10,27 +10,40 @@ pub mod components;
 pub mod physics;
 
 mod ai;
+mod animation;
+mod camera;
+mod font;
 mod graphics;
+mod hud;
 mod input;
+mod lighting;
 mod map;
 mod math;
+mod notes;
 mod player;
+mod postprocess;
+mod renderer;
+mod screenshot;
+mod settings;
 mod sound;
 mod spawner;
 mod state;
+mod time;
 
+use font::Font;
 use game_loop::{
     game_loop,
     winit::{dpi::LogicalSize, event_loop::EventLoop, window::WindowBuilder},
 };
 use pixels::{
     wgpu::{Color, RequestAdapterOptions},
-    Error, Pixels, PixelsBuilder, SurfaceTexture,
+    Error, Pixels, PixelsBuilder, SurfaceTexture, TextureError,
 };
+use settings::Display;
 
-const WIDTH: usize = 384;
-const HEIGHT: usize = 216;
 const TITLE: &str = "Scawy";
+/// How many times per second the game logic runs. Frames are drawn as often as possible
+const UPDATES_PER_SECOND: u32 = 60;
 const DEBUG: bool = cfg!(debug_assertions);
 
 const ASSETS_FOLDER: &str = "assets";
@@ -38,24 +51,44 @@ pub mod prelude {
     pub use crate::components;
     pub use crate::math::*;
     pub use crate::physics;
+    pub use crate::time::Time;
     pub use log::*;
 
-    pub const FPS: u32 = 144;
     pub const PPU: f32 = 16.;
-    pub const TIMESTEP: f32 = 1. / FPS as f32;
 }
 
 pub struct Context {
     pub assets: AssetCache,
     pub input: KeyboardInput,
     pub snd: AudioManager,
+    /// How far between the previous and the next update the current frame is, from 0 to 1
+    pub alpha: f32,
+    /// Seconds it took to draw the last frame
+    pub frame_time: f32,
+    pub display: Display,
+    pub font: Font,
+    /// Size of the screen being drawn to in pixels, follows `display`
+    pub size: UVec2,
     request_exit: bool,
+    /// Changes to the state stack, applied once the current state is done updating
+    pop_state: bool,
+    push_state: Option<Box<dyn state::State>>,
 }
 
 impl Context {
     pub fn request_exit(&mut self) {
         self.request_exit = true;
     }
+
+    /// Puts a state on top of the current one, like a menu or a note being read
+    pub fn push_state(&mut self, state: Box<dyn state::State>) {
+        self.push_state = Some(state);
+    }
+
+    /// Closes the current state, going back to the one under it
+    pub fn pop_state(&mut self) {
+        self.pop_state = true;
+    }
 }
 
 struct Game {
@@ -64,10 +97,14 @@ struct Game {
     pixels: Pixels,
     exit: bool,
     keys: Vec<game_loop::winit::event::KeyboardInput>,
+    /// Saves the next frame drawn
+    screenshot: bool,
+    /// Size of the window in physical pixels
+    window: UVec2,
 }
 
 impl Game {
-    fn new(pixels: Pixels) -> Self {
+    fn new(pixels: Pixels, size: UVec2, window: UVec2) -> Self {
         let assets = AssetCache::new(ASSETS_FOLDER).expect("Path is not a valid directory");
         let snd = AudioManager::<CpalBackend>::new(AudioManagerSettings::default())
             .expect("failed to init Audio Manager");
@@ -76,7 +113,14 @@ impl Game {
             snd,
             assets,
             input: KeyboardInput::default(),
+            alpha: 0.,
+            frame_time: 0.,
+            display: Display::default(),
+            font: Font::load("assets/font.ron").expect("Could not load font"),
+            size,
             request_exit: false,
+            pop_state: false,
+            push_state: None,
         };
         let default_state = Box::new(state::game::InGame::new(&mut ctx));
 
@@ -86,9 +130,24 @@ impl Game {
             pixels,
             exit: false,
             keys: Vec::default(),
+            screenshot: false,
+            window,
         }
     }
 
+    /// Resizes the screen when the display settings or the window changed what it should be
+    fn apply_display(&mut self) -> Result<(), TextureError> {
+        let size = self.ctx.display.size(self.window);
+        if size == self.ctx.size {
+            return Ok(());
+        }
+
+        self.pixels.resize_buffer(size.x, size.y)?;
+        self.ctx.size = size;
+        info!("Drawing at {}x{}", size.x, size.y);
+        Ok(())
+    }
+
     fn update(&mut self) {
         if self.ctx.request_exit {
             self.exit = true;
@@ -97,13 +156,46 @@ impl Game {
 
         self.ctx.input.capture_keys(&mut self.keys);
 
+        // Escape backs out of overlays first, and quits from the game itself
         if self.ctx.input.pressed(KeyCode::Escape) {
-            self.exit = true;
+            if self.state.has_overlay() {
+                self.state.pop();
+            } else {
+                self.exit = true;
+            }
             return;
         }
 
+        if self.ctx.input.pressed(KeyCode::F12) {
+            self.screenshot = true;
+        }
+
+        let display = &mut self.ctx.display;
+        if self.ctx.input.pressed(KeyCode::F5) {
+            display.next_height();
+        }
+        if self.ctx.input.pressed(KeyCode::F6) {
+            display.next_aspect();
+        }
+        if self.ctx.input.pressed(KeyCode::F7) {
+            display.change_fov(-5.);
+        }
+        if self.ctx.input.pressed(KeyCode::F8) {
+            display.change_fov(5.);
+        }
+        if let Err(err) = self.apply_display() {
+            error!("uh oh! screen resize failed: {err}");
+        }
+
         let active_state = self.state.peek();
         active_state.update(&mut self.ctx);
+
+        if std::mem::take(&mut self.ctx.pop_state) {
+            self.state.pop();
+        }
+        if let Some(state) = self.ctx.push_state.take() {
+            self.state.push(state);
+        }
     }
 
     fn draw(&mut self) {
@@ -114,8 +206,15 @@ impl Game {
             *byte = if i % 4 == 3 { 255 } else { 8 };
         }
 
-        let active_state = self.state.peek();
-        active_state.draw(&mut self.ctx, screen);
+        self.state.draw(&mut self.ctx, screen);
+
+        if self.screenshot {
+            self.screenshot = false;
+            match screenshot::take(screen, self.ctx.size.x, self.ctx.size.y) {
+                Ok(path) => info!("Saved screenshot to {}", path.display()),
+                Err(err) => error!("Screenshot could not be saved: {err}"),
+            }
+        }
     }
 }
 
@@ -123,19 +222,16 @@ pub fn idx(x: u32, y: u32, width: u32) -> usize {
     (y * width + x) as usize
 }
 
-/// Calculates the nearest tick value from seconds
-pub fn ticks(seconds: f32) -> u32 {
-    (FPS as f32 * seconds) as u32
-}
-
 fn main() -> Result<(), Error> {
     env_logger::Builder::new()
         .filter(None, LevelFilter::Warn)
         .init();
 
     let event_loop = EventLoop::new();
+    let display = Display::default();
+    let size = display.size(uvec2(16, 9));
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let size = LogicalSize::new(size.x as f64, size.y as f64);
         WindowBuilder::new()
             //.with_fullscreen(Some(game_loop::winit::window::Fullscreen::Borderless(None)))
             .with_title(TITLE)
@@ -148,7 +244,7 @@ fn main() -> Result<(), Error> {
     let win_size = window.inner_size();
     let surface_texture = SurfaceTexture::new(win_size.width, win_size.height, &window);
 
-    let pixels = PixelsBuilder::new(WIDTH as u32, HEIGHT as u32, surface_texture)
+    let pixels = PixelsBuilder::new(size.x, size.y, surface_texture)
         .request_adapter_options(RequestAdapterOptions {
             power_preference: pixels::wgpu::PowerPreference::HighPerformance,
             ..Default::default()
@@ -162,7 +258,10 @@ fn main() -> Result<(), Error> {
         .present_mode(pixels::wgpu::PresentMode::AutoNoVsync)
         .build()?;
 
-    let game = Game::new(pixels);
+    let mut game = Game::new(pixels, size, uvec2(win_size.width, win_size.height));
+    if let Err(err) = game.apply_display() {
+        error!("uh oh! screen resize failed: {err}");
+    }
 
     let mut frames_drawn = 0;
     let mut start = Instant::now();
@@ -171,12 +270,14 @@ fn main() -> Result<(), Error> {
         event_loop,
         window,
         game,
-        FPS,
+        UPDATES_PER_SECOND,
         0.1,
         move |g| {
             g.game.update();
         },
         move |g| {
+            g.game.ctx.alpha = g.blending_factor() as f32;
+            g.game.ctx.frame_time = g.last_frame_time() as f32;
             g.game.draw();
 
             if let Err(err) = g.game.pixels.render() {
@@ -210,6 +311,11 @@ fn main() -> Result<(), Error> {
                             error!("uh oh! window resize failed: {err}");
                             g.exit();
                         }
+
+                        g.game.window = uvec2(size.width, size.height);
+                        if let Err(err) = g.game.apply_display() {
+                            error!("uh oh! screen resize failed: {err}");
+                        }
                     }
                     WindowEvent::KeyboardInput { input, .. } => {
                         g.game.keys.push(*input);
@@ -1,6 +1,6 @@
-use std::fs::File;
+use std::{collections::HashMap, fs::File};
 
-use crate::{idx, prelude::*};
+use crate::{graphics::TextureRegistry, idx, lighting::Atmosphere, prelude::*};
 use bevy_ecs::system::{Commands, Resource};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
@@ -9,11 +9,29 @@ pub enum Entity {
     Generator,
     Battery,
     Exit,
+    Nest,
+    Lamp,
+    Trigger(TriggerDef),
+}
+
+/// Trigger placed by a character in a room prefab, set in `assets/rooms.ron`
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct TriggerDef {
+    /// Size of the circle around the tile's centre that sets it off
+    pub radius: f32,
+    pub action: components::TriggerAction,
+    #[serde(default)]
+    pub once: bool,
 }
 
 impl Entity {
-    pub fn spawn(&self, cmd: &mut Commands, pos: Vec2) -> bevy_ecs::entity::Entity {
-        match *self {
+    pub fn spawn(
+        &self,
+        cmd: &mut Commands,
+        textures: &mut TextureRegistry,
+        pos: Vec2,
+    ) -> bevy_ecs::entity::Entity {
+        match self {
             Self::Note => cmd
                 .spawn((
                     components::Transform {
@@ -21,10 +39,12 @@ impl Entity {
                         ..Default::default()
                     },
                     components::Sprite {
-                        texture: "note".into(),
+                        texture: Some(textures.id("note")),
                         ..Default::default()
                     },
-                    components::Interactable,
+                    components::Interactable {
+                        prompt: "read note",
+                    },
                 ))
                 .id(),
             Self::Generator => cmd
@@ -34,14 +54,17 @@ impl Entity {
                         ..Default::default()
                     },
                     components::Sprite {
-                        texture: "generator".into(),
+                        texture: Some(textures.id("generator")),
                         ..Default::default()
                     },
                     components::Collider {
-                        size: Vec2::splat(2.5),
+                        size: Vec2::splat(0.8),
+                        ..Default::default()
                     },
                     components::Generator::default(),
-                    components::Interactable,
+                    components::Interactable {
+                        prompt: "start generator",
+                    },
                 ))
                 .id(),
             Self::Exit => cmd
@@ -51,6 +74,47 @@ impl Entity {
                         ..Default::default()
                     },
                     components::Exit::default(),
+                    components::Trigger::new(components::Shape::Circle(1.)),
+                    components::Light {
+                        radius: 3.,
+                        intensity: 1.5,
+                        flicker: 0.,
+                    },
+                ))
+                .id(),
+            Self::Nest => cmd
+                .spawn((
+                    components::Transform {
+                        pos,
+                        ..Default::default()
+                    },
+                    components::Nest,
+                ))
+                .id(),
+            Self::Lamp => cmd
+                .spawn((
+                    components::Transform {
+                        pos,
+                        ..Default::default()
+                    },
+                    components::Light {
+                        radius: 4.,
+                        intensity: 2.,
+                        flicker: 0.8,
+                    },
+                ))
+                .id(),
+            Self::Trigger(def) => cmd
+                .spawn((
+                    components::Transform {
+                        pos,
+                        ..Default::default()
+                    },
+                    components::Trigger::new(components::Shape::Circle(def.radius)),
+                    components::Scripted {
+                        action: def.action.clone(),
+                        once: def.once,
+                    },
                 ))
                 .id(),
             _ => cmd
@@ -60,14 +124,17 @@ impl Entity {
                         ..Default::default()
                     },
                     components::Sprite {
-                        texture: "power".into(),
+                        texture: Some(textures.id("power")),
                         ..Default::default()
                     },
                     components::Collider {
                         size: Vec2::splat(2.5),
+                        solid: false,
                     },
                     components::Battery { amount: 1 },
-                    components::Interactable,
+                    components::Interactable {
+                        prompt: "pick up battery",
+                    },
                 ))
                 .id(),
         }
@@ -83,11 +150,34 @@ pub enum Tile {
     Exit,
 }
 
+/// What the floor and ceiling of a tile are made of, set per room in `assets/rooms.ron`
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct Look {
+    /// Name of the floor texture
+    pub floor: String,
+    /// Name of the ceiling texture, `None` leaves it open to the sky
+    #[serde(default)]
+    pub ceiling: Option<String>,
+}
+
+impl Default for Look {
+    fn default() -> Self {
+        Self {
+            floor: "floor".to_string(),
+            ceiling: Some("ceil".to_string()),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct Map {
     tiles: Vec<Tile>,
     width: u32,
     height: u32,
+    /// Every look used on the map, the first one is used anywhere nothing else was set
+    looks: Vec<Look>,
+    /// Index into `looks` for every tile
+    tile_looks: Vec<u8>,
 }
 
 impl Map {
@@ -96,6 +186,8 @@ impl Map {
             tiles: vec![Tile::Empty; (width * height) as usize],
             width,
             height,
+            looks: vec![Look::default()],
+            tile_looks: vec![0; (width * height) as usize],
         }
     }
 
@@ -122,12 +214,57 @@ impl Map {
         let idx = crate::idx(x, y, self.width);
         self.tiles.get(idx)
     }
+
+    pub fn looks(&self) -> &[Look] {
+        &self.looks
+    }
+
+    /// Index into `Map::looks` of the tile's floor and ceiling. Outside of the map it's the default
+    pub fn look_id(&self, x: i32, y: i32) -> usize {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return 0;
+        }
+        self.tile_looks[crate::idx(x as u32, y as u32, self.width)] as usize
+    }
+
+    pub fn set_look(&mut self, x: u32, y: u32, look: &Look) -> bool {
+        let idx = crate::idx(x, y, self.width);
+        if idx >= self.tile_looks.len() {
+            warn!("Attempted to set the look of a nonexistent tile");
+            return false;
+        }
+
+        let id = match self.looks.iter().position(|other| other == look) {
+            Some(id) => id,
+            None if self.looks.len() <= u8::MAX as usize => {
+                self.looks.push(look.clone());
+                self.looks.len() - 1
+            }
+            None => {
+                warn!("Too many different looks on one map");
+                return false;
+            }
+        };
+        self.tile_looks[idx] = id as u8;
+        true
+    }
+
+    /// Checks if a tile blocks movement. Anything outside of the map is solid
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return true;
+        }
+        self.get_tile(x as u32, y as u32) != Some(&Tile::Empty)
+    }
 }
 
 pub struct MapGenerator {
     pub map: Map,
     pub spawn: Vec2,
     pub entities: Vec<(Entity, UVec2)>,
+    /// Tiles marked in room prefabs as places a monster may start on
+    pub monster_spawns: Vec<UVec2>,
+    pub atmosphere: Atmosphere,
 }
 
 impl MapGenerator {
@@ -139,6 +276,8 @@ impl MapGenerator {
             map,
             spawn: Vec2::ZERO,
             entities: Vec::new(),
+            monster_spawns: Vec::new(),
+            atmosphere: Atmosphere::default(),
         };
         gen.map.tiles.iter_mut().for_each(|tile| *tile = Tile::Wall);
         gen.build_rooms(&mut rng);
@@ -151,6 +290,7 @@ impl MapGenerator {
         // const MAX_TUNNEL_LEN: u32 = 7;
 
         let room_defs = RoomDefs::load();
+        self.atmosphere = room_defs.atmosphere.clone();
         let possible_starts: Vec<&Room> = room_defs
             .rooms
             .iter()
@@ -167,7 +307,7 @@ impl MapGenerator {
         let pos = UVec2::splat(SIZE / 2);
 
         // Place selected room
-        self.place_room(start_room, pos);
+        self.place_room(start_room, pos, &room_defs);
 
         // // Grab indicies of possible connectors
         // let connectors: Vec<usize> = start_room
@@ -195,7 +335,8 @@ impl MapGenerator {
         //let new_room = ROOM_SMALL;
     }
 
-    fn place_room(&mut self, room: &Room, pos: UVec2) {
+    fn place_room(&mut self, room: &Room, pos: UVec2, defs: &RoomDefs) {
+        let looks = &defs.looks;
         let mut height = 0;
         let mut width = 0;
         let chars: Vec<char> = room
@@ -235,10 +376,27 @@ impl MapGenerator {
                         self.entities.push((Entity::Battery, pos));
                         Tile::Empty
                     }
+                    'M' => {
+                        self.monster_spawns.push(pos);
+                        Tile::Empty
+                    }
+                    'X' => {
+                        self.entities.push((Entity::Nest, pos));
+                        Tile::Empty
+                    }
+                    'L' => {
+                        self.entities.push((Entity::Lamp, pos));
+                        Tile::Empty
+                    }
                     'E' => {
                         self.entities.push((Entity::Exit, pos));
                         Tile::Exit
                     }
+                    c if defs.triggers.contains_key(&c) => {
+                        self.entities
+                            .push((Entity::Trigger(defs.triggers[&c].clone()), pos));
+                        Tile::Empty
+                    }
                     _ => Tile::Wall,
                 };
 
@@ -246,17 +404,51 @@ impl MapGenerator {
                 i += 1;
             }
         }
+
+        // Looks are laid out on a grid the same size as the prefab, anything not in `looks` uses
+        // the room's own
+        let grid: Vec<Vec<char>> = room
+            .looks
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.chars().collect())
+            .collect();
+        let room_look = room.look.and_then(|key| looks.get(&key));
+        for y in 0..height - 1 {
+            for x in 0..width / (height - 1) {
+                let key = grid.get(y as usize).and_then(|row| row.get(x as usize));
+                let look = key.and_then(|key| looks.get(key)).or(room_look);
+                if let Some(look) = look {
+                    self.map.set_look(pos.x + x, pos.y + y, look);
+                }
+            }
+        }
     }
 }
 
 #[derive(Clone, serde::Deserialize, PartialEq)]
 struct Room {
     prefab: String,
+    /// Key into `RoomDefs::looks` for the whole room
+    #[serde(default)]
+    look: Option<char>,
+    /// Keys into `RoomDefs::looks` for single tiles, on a grid matching the prefab
+    #[serde(default)]
+    looks: String,
 }
 
-#[derive(Clone, serde::Deserialize, PartialEq)]
+#[derive(Clone, serde::Deserialize)]
 struct RoomDefs {
     rooms: Vec<Room>,
+    /// Floors and ceilings rooms can use, by the character marking them
+    #[serde(default)]
+    looks: HashMap<char, Look>,
+    /// Triggers rooms can place, by the character marking them
+    #[serde(default)]
+    triggers: HashMap<char, TriggerDef>,
+    #[serde(default)]
+    atmosphere: Atmosphere,
 }
 
 impl RoomDefs {
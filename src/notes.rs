@@ -0,0 +1,108 @@
+use assets_manager::{loader::RonLoader, Asset, AssetCache};
+use bevy_ecs::prelude::*;
+
+use crate::{
+    hud,
+    player::Interact,
+    prelude::*,
+    state::game::{add_event, GameData},
+};
+
+/// What a note says, set in `assets/notes.ron`
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct NoteDef {
+    pub title: String,
+    pub body: String,
+    /// Name of a texture shown with the note
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+/// Every note there is to find
+#[derive(Resource, Default, Clone, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Notes(pub Vec<NoteDef>);
+
+impl Asset for Notes {
+    const EXTENSION: &'static str = "ron";
+    type Loader = RonLoader;
+}
+
+impl Notes {
+    pub fn load(assets: &AssetCache) -> Self {
+        match assets.load::<Notes>("notes") {
+            Ok(notes) => notes.cloned(),
+            Err(err) => {
+                warn!("Could not load notes, they'll all be blank: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Ids of the notes that haven't been read yet, in the order they should be found
+    pub fn unread<'a>(&'a self, journal: &'a Journal) -> impl Iterator<Item = usize> + 'a {
+        (0..self.0.len()).filter(|id| !journal.read.contains(id))
+    }
+}
+
+/// Notes the player has read, in the order they found them
+#[derive(Resource, Default)]
+pub struct Journal {
+    pub read: Vec<usize>,
+}
+
+/// Sent when the player picks up a note, so it can be shown
+pub struct ReadNote(pub usize);
+
+pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
+    world.init_resource::<Journal>();
+    add_event::<ReadNote>(world, schedule);
+
+    schedule.add_system(read_note);
+}
+
+fn read_note(
+    mut int_reader: EventReader<Interact>,
+    mut note_writer: EventWriter<ReadNote>,
+    mut toast_writer: EventWriter<hud::Toast>,
+    mut data: ResMut<GameData>,
+    mut journal: ResMut<Journal>,
+    query: Query<&components::Note>,
+) {
+    for event in int_reader.iter() {
+        let Ok(note) = query.get(event.entity) else {
+            continue;
+        };
+
+        if !journal.read.contains(&note.id) {
+            journal.read.push(note.id);
+            data.notes_taken += 1;
+            toast_writer.send(hud::Toast("Added to journal (J)".to_string()));
+        }
+        note_writer.send(ReadNote(note.id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_asset_parses() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/notes.ron");
+        let notes: Notes = ron::de::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+        assert!(!notes.0.is_empty());
+    }
+
+    #[test]
+    fn read_notes_arent_handed_out_again() {
+        let note = NoteDef {
+            title: String::new(),
+            body: String::new(),
+            image: None,
+        };
+        let notes = Notes(vec![note; 4]);
+        let journal = Journal { read: vec![2, 0] };
+        assert_eq!(notes.unread(&journal).collect::<Vec<_>>(), [1, 3]);
+    }
+}
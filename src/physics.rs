@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
     map,
     prelude::*,
@@ -5,34 +7,194 @@ use crate::{
 };
 use bevy_ecs::prelude::*;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HitTarget {
+    Entity(Entity),
+    /// Map tile at the position
+    Tile(IVec2),
+}
+
 pub struct CollisionHit {
     pub entity: Entity,
-    pub hit_entity: Entity,
+    pub target: HitTarget,
+    /// Points away from the target
+    pub normal: Vec2,
+    /// How far the entity overlaps, or would have moved into, the target
+    pub depth: f32,
 }
 
 impl CollisionHit {
     pub fn contains(&self, ent: Entity) -> bool {
-        self.entity == ent || self.hit_entity == ent
+        self.entity == ent || self.target == HitTarget::Entity(ent)
+    }
+}
+
+/// Wall a moving box ran into
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TileContact {
+    pub tile: IVec2,
+    pub normal: Vec2,
+    pub depth: f32,
+}
+
+pub struct Slide {
+    pub pos: Vec2,
+    pub contacts: Vec<TileContact>,
+}
+
+pub enum TriggerState {
+    Enter,
+    Stay,
+    Exit,
+}
+
+pub struct TriggerEvent {
+    pub trigger: Entity,
+    pub entity: Entity,
+    pub state: TriggerState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    /// Face crossed while stepping along the x axis
+    X,
+    /// Face crossed while stepping along the y axis
+    Y,
+}
+
+pub struct RayHit {
+    pub tile: IVec2,
+    /// Distance along the ray in multiples of its direction.
+    /// A normalized direction gives the distance in tiles
+    pub dist: f32,
+    pub side: Side,
+    pub point: Vec2,
+}
+
+pub struct EntityRayHit {
+    pub entity: Entity,
+    pub dist: f32,
+    pub point: Vec2,
+}
+
+#[derive(Resource)]
+pub struct PhysicsSettings {
+    /// Push overlapping moving entities away from each other
+    pub push_apart: bool,
+    /// Fraction of the overlap resolved each tick
+    pub push_strength: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            push_apart: true,
+            push_strength: 0.2,
+        }
+    }
+}
+
+/// Broadphase that buckets colliders by the map tiles they cover,
+/// so only entities sharing a tile need to be tested against each other
+#[derive(Resource, Default)]
+pub struct SpatialGrid {
+    cells: HashMap<IVec2, Vec<Entity>>,
+}
+
+impl SpatialGrid {
+    pub fn clear(&mut self) {
+        self.cells.values_mut().for_each(Vec::clear);
+    }
+
+    pub fn insert(&mut self, ent: Entity, pos: Vec2, size: Vec2) {
+        let (min, max) = covered_tiles(pos, size);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.cells.entry(ivec2(x, y)).or_default().push(ent);
+            }
+        }
+    }
+
+    /// Entities with a collider sharing a tile with the box
+    pub fn query(&self, pos: Vec2, size: Vec2) -> HashSet<Entity> {
+        let (min, max) = covered_tiles(pos, size);
+
+        let mut found = HashSet::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                if let Some(cell) = self.cells.get(&ivec2(x, y)) {
+                    found.extend(cell.iter().copied());
+                }
+            }
+        }
+        found
     }
 }
 
 pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
     add_event::<CollisionHit>(world, schedule);
+    add_event::<TriggerEvent>(world, schedule);
+    world.init_resource::<SpatialGrid>();
+    world.init_resource::<PhysicsSettings>();
     schedule.add_systems(
-        (apply_movement, detect_collision.before(apply_movement)).in_base_set(CoreSet::Physics),
+        (
+            update_grid.before(detect_collision),
+            detect_collision.before(apply_movement),
+            apply_movement,
+            push_apart.after(apply_movement),
+            update_triggers.after(push_apart),
+        )
+            .in_base_set(CoreSet::Physics),
     );
 }
 
-fn apply_movement(mut move_query: Query<(&mut components::Transform, &mut components::Movement)>) {
-    for (mut loc, mut movement) in move_query.iter_mut() {
-        loc.pos += velocity(movement.velocity(), movement.speed());
+fn update_grid(
+    mut grid: ResMut<SpatialGrid>,
+    query: Query<(Entity, &components::Transform, &components::Collider)>,
+) {
+    grid.clear();
+    for (ent, trans, col) in query.iter() {
+        grid.insert(ent, trans.pos, col.size);
+    }
+}
+
+fn apply_movement(
+    mut event_writer: EventWriter<CollisionHit>,
+    map: Res<map::Map>,
+    time: Res<Time>,
+    mut move_query: Query<(
+        Entity,
+        &mut components::Transform,
+        &mut components::Movement,
+        Option<&components::Collider>,
+    )>,
+) {
+    for (ent, mut loc, mut movement, col) in move_query.iter_mut() {
+        let delta = velocity(movement.velocity(), movement.speed(), time.delta());
         movement.set_velocity(Vec2::ZERO);
+
+        let Some(col) = col else {
+            loc.pos += delta;
+            continue;
+        };
+
+        let slide = move_and_slide(&map, loc.pos, col.size, delta);
+        for contact in slide.contacts {
+            event_writer.send(CollisionHit {
+                entity: ent,
+                target: HitTarget::Tile(contact.tile),
+                normal: contact.normal,
+                depth: contact.depth,
+            });
+        }
+        loc.pos = slide.pos;
     }
 }
 
 fn detect_collision(
     mut event_writer: EventWriter<CollisionHit>,
-    map: Res<map::Map>,
+    grid: Res<SpatialGrid>,
+    time: Res<Time>,
     mut move_query: Query<(Entity, &mut components::Movement)>,
     collider_query: Query<(Entity, &components::Transform, &components::Collider)>,
 ) {
@@ -41,41 +203,606 @@ fn detect_collision(
             continue;
         };
 
-        let new_pos = trans_a.pos + velocity(movement.velocity(), movement.speed());
+        let step = |movement: &components::Movement| {
+            trans_a.pos + velocity(movement.velocity(), movement.speed(), time.delta())
+        };
+        let mut new_pos = step(&movement);
+
+        // Cover both where the entity is and where it's going
+        let mut nearby = grid.query(trans_a.pos, col_a.size);
+        nearby.extend(grid.query(new_pos, col_a.size));
 
-        if let Some(tile) = map.get_tile(new_pos.x as u32, new_pos.y as u32) {
-            if *tile == map::Tile::Empty {
+        for (ent_b, trans_b, col_b) in collider_query.iter_many(&nearby) {
+            if ent_a == ent_b {
                 continue;
             }
-            let event = CollisionHit {
-                entity: ent_a,
-                // Use placeholder if hit object is a tile
-                // because I don't feel like wrapping in an option
-                hit_entity: Entity::PLACEHOLDER,
+
+            if let Some((normal, depth)) = overlap(trans_a.pos, col_a.size, trans_b.pos, col_b.size)
+            {
+                event_writer.send(CollisionHit {
+                    entity: ent_a,
+                    target: HitTarget::Entity(ent_b),
+                    normal,
+                    depth,
+                });
+            }
+
+            if !col_b.solid {
+                continue;
+            }
+
+            // Only the part moving further in is blocked so overlapping entities can still separate
+            if let Some((normal, _)) = overlap(new_pos, col_a.size, trans_b.pos, col_b.size) {
+                movement.block(normal);
+                new_pos = step(&movement);
+            }
+        }
+    }
+}
+
+fn push_apart(
+    settings: Res<PhysicsSettings>,
+    grid: Res<SpatialGrid>,
+    map: Res<map::Map>,
+    mut query: Query<
+        (Entity, &mut components::Transform, &components::Collider),
+        With<components::Movement>,
+    >,
+) {
+    // Entities may have moved a little since the grid was built
+    const MARGIN: f32 = 0.25;
+
+    if !settings.push_apart {
+        return;
+    }
+
+    let mut pushes: HashMap<Entity, Vec2> = HashMap::new();
+
+    for (ent_a, trans_a, col_a) in query.iter() {
+        for ent_b in grid.query(trans_a.pos, col_a.size + MARGIN) {
+            // Handle every pair once
+            if ent_b <= ent_a {
+                continue;
+            }
+            let Ok((_, trans_b, col_b)) = query.get(ent_b) else {
+                continue;
             };
-            event_writer.send(event);
-            movement.set_velocity(Vec2::ZERO);
+
+            let Some((normal, depth)) = overlap(trans_a.pos, col_a.size, trans_b.pos, col_b.size)
+            else {
+                continue;
+            };
+
+            let push = normal * depth * settings.push_strength / 2.;
+
+            *pushes.entry(ent_a).or_default() += push;
+            *pushes.entry(ent_b).or_default() -= push;
         }
+    }
 
-        for (ent_b, trans_b, col_b) in collider_query.iter() {
-            if collide(trans_a.pos, col_a.size, trans_b.pos, col_b.size) {
-                let event = CollisionHit {
-                    entity: ent_a,
-                    hit_entity: ent_b,
-                };
-                event_writer.send(event);
+    for (ent, push) in pushes {
+        let Ok((_, mut trans, col)) = query.get_mut(ent) else {
+            continue;
+        };
+        trans.pos = move_and_slide(&map, trans.pos, col.size, push).pos;
+    }
+}
+
+fn update_triggers(
+    mut event_writer: EventWriter<TriggerEvent>,
+    grid: Res<SpatialGrid>,
+    mut trigger_query: Query<(Entity, &components::Transform, &mut components::Trigger)>,
+    mover_query: Query<
+        (Entity, &components::Transform, &components::Collider),
+        With<components::Movement>,
+    >,
+) {
+    // Entities may have moved a little since the grid was built
+    const MARGIN: f32 = 0.25;
+
+    for (trigger_ent, trigger_trans, mut trigger) in trigger_query.iter_mut() {
+        let bounds = match trigger.shape {
+            components::Shape::Box(size) => size,
+            components::Shape::Circle(radius) => Vec2::splat(radius * 2.),
+        };
+
+        let mut inside = HashSet::new();
+        for (ent, trans, col) in
+            mover_query.iter_many(grid.query(trigger_trans.pos, bounds + MARGIN))
+        {
+            if overlaps_shape(trigger_trans.pos, trigger.shape, trans.pos, col.size) {
+                inside.insert(ent);
+            }
+        }
+
+        for ent in inside.iter() {
+            let state = if trigger.contains(*ent) {
+                TriggerState::Stay
+            } else {
+                TriggerState::Enter
+            };
+            event_writer.send(TriggerEvent {
+                trigger: trigger_ent,
+                entity: *ent,
+                state,
+            });
+        }
+        for ent in trigger.inside_mut().difference(&inside) {
+            event_writer.send(TriggerEvent {
+                trigger: trigger_ent,
+                entity: *ent,
+                state: TriggerState::Exit,
+            });
+        }
+
+        *trigger.inside_mut() = inside;
+    }
+}
+
+/// Steps through the map grid from `origin` along `dir` until a non empty tile is hit.
+/// Returns `None` if the ray leaves the map or travels further than `max_dist`
+pub fn raycast(map: &map::Map, origin: Vec2, dir: Vec2, max_dist: f32) -> Option<RayHit> {
+    // followed this tutorial lmao https://lodev.org/cgtutor/raycasting.html
+    let mut tile = origin.floor().as_ivec2();
+
+    let delta_dist = (1. / dir).abs();
+    let step = ivec2(
+        if dir.x < 0. { -1 } else { 1 },
+        if dir.y < 0. { -1 } else { 1 },
+    );
+
+    // calculate side distances
+    let mut side_dist = vec2(
+        if dir.x < 0. {
+            origin.x - tile.x as f32
+        } else {
+            tile.x as f32 + 1. - origin.x
+        },
+        if dir.y < 0. {
+            origin.y - tile.y as f32
+        } else {
+            tile.y as f32 + 1. - origin.y
+        },
+    ) * delta_dist;
+
+    // DDA
+    loop {
+        let side = if side_dist.x < side_dist.y {
+            side_dist.x += delta_dist.x;
+            tile.x += step.x;
+            Side::X
+        } else {
+            side_dist.y += delta_dist.y;
+            tile.y += step.y;
+            Side::Y
+        };
+
+        let dist = match side {
+            Side::X => side_dist.x - delta_dist.x,
+            Side::Y => side_dist.y - delta_dist.y,
+        };
+        // Tiles are stored row after row, so going off the right edge would land on the next row
+        if dist > max_dist
+            || tile.x.is_negative()
+            || tile.y.is_negative()
+            || tile.x as u32 >= map.width()
+        {
+            return None;
+        }
+
+        match map.get_tile(tile.x as u32, tile.y as u32) {
+            Some(map::Tile::Empty) => (),
+            Some(_) => {
+                return Some(RayHit {
+                    tile,
+                    dist,
+                    side,
+                    point: origin + dir * dist,
+                })
             }
+            None => return None,
+        }
+    }
+}
+
+/// Finds the closest collider hit along the ray that isn't further than `max_dist`
+pub fn raycast_entities<'a>(
+    origin: Vec2,
+    dir: Vec2,
+    max_dist: f32,
+    colliders: impl IntoIterator<Item = (Entity, &'a components::Transform, &'a components::Collider)>,
+) -> Option<EntityRayHit> {
+    colliders
+        .into_iter()
+        .filter_map(|(entity, trans, col)| {
+            let dist = ray_box(origin, dir, trans.pos, col.size)?;
+            (dist <= max_dist).then_some(EntityRayHit {
+                entity,
+                dist,
+                point: origin + dir * dist,
+            })
+        })
+        .min_by(|a, b| a.dist.total_cmp(&b.dist))
+}
+
+/// Distance along the ray to where it enters a box centered on `pos`
+fn ray_box(origin: Vec2, dir: Vec2, pos: Vec2, size: Vec2) -> Option<f32> {
+    let inverse = 1. / dir;
+    let t_0 = (pos - size / 2. - origin) * inverse;
+    let t_1 = (pos + size / 2. - origin) * inverse;
+
+    let near = t_0.min(t_1).max_element();
+    let far = t_0.max(t_1).min_element();
+
+    if near > far || far < 0. {
+        return None;
+    }
+    Some(near.max(0.))
+}
+
+/// Checks if a box centered on `pos` overlaps a shape
+pub fn overlaps_shape(shape_pos: Vec2, shape: components::Shape, pos: Vec2, size: Vec2) -> bool {
+    match shape {
+        components::Shape::Box(shape_size) => collide(shape_pos, shape_size, pos, size),
+        components::Shape::Circle(radius) => {
+            let half_size = size / 2.;
+            let closest = shape_pos.clamp(pos - half_size, pos + half_size);
+            closest.distance_squared(shape_pos) < radius * radius
+        }
+    }
+}
+
+/// Tile coordinates of the first and last tiles covered by a box
+fn covered_tiles(pos: Vec2, size: Vec2) -> (IVec2, IVec2) {
+    let half_size = size / 2.;
+    let min = (pos - half_size).floor().as_ivec2();
+    let max = (pos + half_size).ceil().as_ivec2() - 1;
+    (min, max)
+}
+
+/// Moves a box centered on `pos` by `delta` one axis at a time,
+/// so hitting a wall only stops the blocked axis and the box slides along it.
+pub fn move_and_slide(map: &map::Map, pos: Vec2, size: Vec2, delta: Vec2) -> Slide {
+    let mut slide = Slide {
+        pos,
+        contacts: Vec::new(),
+    };
+    for axis in 0..2 {
+        if let Some(contact) = slide_axis(map, &mut slide.pos, size / 2., delta[axis], axis) {
+            slide.contacts.push(contact);
         }
     }
+    slide
 }
 
+fn slide_axis(
+    map: &map::Map,
+    pos: &mut Vec2,
+    half_size: Vec2,
+    delta: f32,
+    axis: usize,
+) -> Option<TileContact> {
+    // Keeps boxes from resting exactly on a tile edge and counting as inside of it
+    const SKIN: f32 = 0.001;
+
+    if delta == 0. {
+        return None;
+    }
+
+    let start = pos[axis];
+    pos[axis] += delta;
+
+    let (min, max) = covered_tiles(*pos, half_size * 2.);
+
+    // Find the closest wall in the direction of movement
+    let mut wall: Option<IVec2> = None;
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            if !map.is_solid(x, y) {
+                continue;
+            }
+
+            let tile = ivec2(x, y);
+            let closer = match wall {
+                Some(wall) if delta > 0. => tile[axis] < wall[axis],
+                Some(wall) => tile[axis] > wall[axis],
+                None => true,
+            };
+            if closer {
+                wall = Some(tile);
+            }
+        }
+    }
+
+    let wall = wall?;
+    let edge = wall[axis] as f32;
+    let moved_to = pos[axis];
+
+    // Snap flush against the wall, but never push back past where the box started
+    pos[axis] = if delta > 0. {
+        (edge - half_size[axis] - SKIN).max(start)
+    } else {
+        (edge + 1. + half_size[axis] + SKIN).min(start)
+    };
+
+    let mut normal = Vec2::ZERO;
+    normal[axis] = -delta.signum();
+
+    Some(TileContact {
+        tile: wall,
+        normal,
+        depth: (moved_to - pos[axis]).abs(),
+    })
+}
+
+/// Normal pointing from box b to box a along the axis they overlap the least on,
+/// along with how deep they overlap
+pub fn overlap(pos_a: Vec2, size_a: Vec2, pos_b: Vec2, size_b: Vec2) -> Option<(Vec2, f32)> {
+    let overlap = (size_a + size_b) / 2. - (pos_a - pos_b).abs();
+    if overlap.x <= 0. || overlap.y <= 0. {
+        return None;
+    }
+
+    let dir = (pos_a - pos_b).signum();
+    if overlap.x < overlap.y {
+        Some((vec2(dir.x, 0.), overlap.x))
+    } else {
+        Some((vec2(0., dir.y), overlap.y))
+    }
+}
+
+/// Checks if two boxes centered on their positions overlap
 pub fn collide(pos_a: Vec2, size_a: Vec2, pos_b: Vec2, size_b: Vec2) -> bool {
-    pos_a.x + size_a.x > pos_b.x
-        && pos_a.x < pos_b.x + size_b.x
-        && pos_a.y + size_a.y > pos_b.y
-        && pos_a.y < pos_b.y + size_b.y
+    let dist = (pos_a - pos_b).abs();
+    let extents = (size_a + size_b) / 2.;
+    dist.x < extents.x && dist.y < extents.y
 }
 
-fn velocity(vel: Vec2, speed: f32) -> Vec2 {
-    vel * speed * TIMESTEP * PPU
+fn velocity(vel: Vec2, speed: f32, delta: f32) -> Vec2 {
+    vel * speed * delta * PPU
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Map, Tile};
+
+    /// Builds a map from rows where `#` is a wall and anything else is empty
+    fn map(rows: &[&str]) -> Map {
+        let mut map = Map::new(rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    map.set_tile(x as u32, y as u32, Tile::Wall);
+                }
+            }
+        }
+        map
+    }
+
+    const SIZE: Vec2 = Vec2::splat(0.5);
+
+    #[test]
+    fn moves_freely_in_open_space() {
+        let map = map(&["#####", "#---#", "#---#", "#---#", "#####"]);
+
+        let slide = move_and_slide(&map, vec2(2.5, 2.5), SIZE, vec2(0.3, -0.2));
+        assert_eq!(slide.pos, vec2(2.8, 2.3));
+        assert!(slide.contacts.is_empty());
+    }
+
+    #[test]
+    fn stops_flush_against_wall() {
+        let map = map(&["#####", "#---#", "#---#", "#---#", "#####"]);
+
+        let slide = move_and_slide(&map, vec2(3.5, 2.5), SIZE, vec2(0.5, 0.));
+        assert!(slide.pos.x > 3.7 && slide.pos.x < 3.75);
+        assert_eq!(slide.pos.y, 2.5);
+
+        let contact = slide.contacts[0];
+        assert_eq!(contact.tile, ivec2(4, 2));
+        assert_eq!(contact.normal, Vec2::NEG_X);
+        assert!(contact.depth > 0.25 && contact.depth < 0.26);
+    }
+
+    #[test]
+    fn slides_along_wall() {
+        let map = map(&["#####", "#---#", "#---#", "#---#", "#####"]);
+
+        let pos = move_and_slide(&map, vec2(2.5, 1.3), SIZE, vec2(0.2, -0.2)).pos;
+        assert_eq!(pos.x, 2.7);
+        assert!(pos.y >= 1.25 && pos.y < 1.3);
+    }
+
+    #[test]
+    fn uses_collider_extents() {
+        // The center stays inside an empty tile but the edge of the box would enter the wall
+        let map = map(&["#####", "#--##", "#---#", "#####"]);
+
+        let pos = move_and_slide(&map, vec2(2.8, 2.5), SIZE, vec2(0., -1.5)).pos;
+        assert!(pos.y > 2.25 && pos.y < 2.3);
+
+        let pos = move_and_slide(&map, vec2(2.3, 2.5), SIZE, vec2(0., -1.5)).pos;
+        assert!(pos.y > 1.25 && pos.y < 1.3);
+    }
+
+    #[test]
+    fn fits_through_corridor() {
+        let map = map(&["#####", "#---#", "##-##", "#---#", "#####"]);
+
+        let pos = move_and_slide(&map, vec2(2.5, 1.5), SIZE, vec2(0., 1.5)).pos;
+        assert_eq!(pos, vec2(2.5, 3.0));
+    }
+
+    #[test]
+    fn blocked_at_corner() {
+        let map = map(&["#####", "#---#", "##-##", "#---#", "#####"]);
+
+        // Moving into the corridor while off center catches on the corner
+        let pos = move_and_slide(&map, vec2(1.5, 1.5), SIZE, vec2(0., 1.)).pos;
+        assert!(pos.y > 1.7 && pos.y < 1.75);
+    }
+
+    #[test]
+    fn overlap_separates_along_shallowest_axis() {
+        let (normal, depth) = overlap(vec2(0.9, 0.1), Vec2::ONE, Vec2::ZERO, Vec2::ONE).unwrap();
+        assert_eq!(normal, Vec2::X);
+        assert!((depth - 0.1).abs() < 0.0001);
+
+        assert!(overlap(vec2(1.1, 0.), Vec2::ONE, Vec2::ZERO, Vec2::ONE).is_none());
+    }
+
+    #[test]
+    fn slides_along_solid_entity() {
+        let mut world = World::default();
+        world.insert_resource(Time::new(60));
+        world.init_resource::<SpatialGrid>();
+        world.init_resource::<Events<CollisionHit>>();
+
+        let mut movement = components::Movement::with_speed(0.5);
+        movement.set_velocity(vec2(1., 1.));
+        let mover = world
+            .spawn((
+                components::Transform {
+                    pos: vec2(1., 1.),
+                    ..Default::default()
+                },
+                movement,
+                components::Collider {
+                    size: SIZE,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        world.spawn((
+            components::Transform {
+                pos: vec2(1.55, 1.),
+                ..Default::default()
+            },
+            components::Collider {
+                size: SIZE,
+                ..Default::default()
+            },
+        ));
+
+        let mut schedule = Schedule::new();
+        schedule.add_systems((update_grid, detect_collision).chain());
+        schedule.run(&mut world);
+
+        let vel = world.get::<components::Movement>(mover).unwrap().velocity();
+        assert_eq!(vel.x, 0.);
+        assert_eq!(vel.y, vec2(1., 1.).normalize().y);
+    }
+
+    #[test]
+    fn grid_finds_only_nearby_entities() {
+        let mut grid = SpatialGrid::default();
+        let near = Entity::from_raw(0);
+        let far = Entity::from_raw(1);
+        grid.insert(near, vec2(2.5, 2.5), SIZE);
+        grid.insert(far, vec2(10.5, 10.5), SIZE);
+
+        let found = grid.query(vec2(2.9, 2.5), SIZE);
+        assert!(found.contains(&near));
+        assert!(!found.contains(&far));
+    }
+
+    #[test]
+    fn grid_buckets_entity_in_every_covered_tile() {
+        let mut grid = SpatialGrid::default();
+        let ent = Entity::from_raw(0);
+        grid.insert(ent, vec2(3., 3.), SIZE);
+
+        for pos in [
+            vec2(2.5, 2.5),
+            vec2(3.5, 2.5),
+            vec2(2.5, 3.5),
+            vec2(3.5, 3.5),
+        ] {
+            assert!(grid.query(pos, Vec2::splat(0.1)).contains(&ent));
+        }
+    }
+
+    #[test]
+    fn box_overlaps_circle_at_edge() {
+        let circle = components::Shape::Circle(1.);
+
+        assert!(overlaps_shape(Vec2::ZERO, circle, vec2(1.2, 0.), SIZE));
+        assert!(!overlaps_shape(Vec2::ZERO, circle, vec2(1.3, 0.), SIZE));
+        // Corners are further away than the sides
+        assert!(!overlaps_shape(Vec2::ZERO, circle, vec2(1., 1.), SIZE));
+    }
+
+    #[test]
+    fn raycast_hits_wall() {
+        let map = map(&["#####", "#---#", "#---#", "#---#", "#####"]);
+
+        let hit = raycast(&map, vec2(1.5, 2.5), Vec2::X, 10.).unwrap();
+        assert_eq!(hit.tile, ivec2(4, 2));
+        assert_eq!(hit.side, Side::X);
+        assert_eq!(hit.dist, 2.5);
+        assert_eq!(hit.point, vec2(4., 2.5));
+
+        let hit = raycast(&map, vec2(2.5, 2.5), Vec2::NEG_Y, 10.).unwrap();
+        assert_eq!(hit.tile, ivec2(2, 0));
+        assert_eq!(hit.side, Side::Y);
+        assert_eq!(hit.dist, 1.5);
+    }
+
+    #[test]
+    fn raycast_stops_at_max_dist() {
+        let map = map(&["#####", "#---#", "#---#", "#---#", "#####"]);
+
+        assert!(raycast(&map, vec2(1.5, 2.5), Vec2::X, 2.).is_none());
+    }
+
+    #[test]
+    fn raycast_misses_outside_of_map() {
+        let map = map(&["---", "---", "---"]);
+
+        assert!(raycast(&map, vec2(1.5, 1.5), vec2(1., 0.3), 100.).is_none());
+    }
+
+    #[test]
+    fn raycast_doesnt_wrap_onto_the_next_row() {
+        let map = map(&["---", "#--"]);
+
+        assert!(raycast(&map, vec2(0.5, 0.5), Vec2::X, 100.).is_none());
+    }
+
+    #[test]
+    fn raycast_finds_closest_entity() {
+        let col = components::Collider {
+            size: Vec2::splat(1.),
+            ..Default::default()
+        };
+        let near = components::Transform {
+            pos: vec2(3., 0.),
+            ..Default::default()
+        };
+        let far = components::Transform {
+            pos: vec2(6., 0.),
+            ..Default::default()
+        };
+        let colliders = [
+            (Entity::from_raw(1), &far, &col),
+            (Entity::from_raw(0), &near, &col),
+        ];
+
+        let hit = raycast_entities(Vec2::ZERO, Vec2::X, 10., colliders).unwrap();
+        assert_eq!(hit.entity, Entity::from_raw(0));
+        assert_eq!(hit.dist, 2.5);
+
+        assert!(raycast_entities(Vec2::ZERO, Vec2::Y, 10., colliders).is_none());
+        assert!(raycast_entities(Vec2::ZERO, Vec2::X, 2., colliders).is_none());
+    }
+
+    #[test]
+    fn outside_of_map_is_solid() {
+        let map = map(&["---", "---", "---"]);
+
+        let pos = move_and_slide(&map, vec2(0.5, 0.5), SIZE, vec2(-1., -1.)).pos;
+        assert!(pos.x > 0.25 && pos.x < 0.3);
+        assert!(pos.y > 0.25 && pos.y < 0.3);
+    }
 }
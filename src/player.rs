@@ -1,21 +1,38 @@
 use std::collections::HashMap;
 
 use crate::{
+    ai, hud,
+    lighting::{self, Spotlight},
     map::Map,
     prelude::*,
     sound,
     state::game::{add_event, Camera, CoreSet, GameData},
-    ticks,
 };
 use bevy_ecs::prelude::*;
 use rand::Rng;
 
 const LIGHT_RANGE: f32 = 16.;
-const INTERACT_RANGE: f32 = 1.5;
+/// Squared distance the player can reach things from
+pub const INTERACT_RANGE: f32 = 1.5;
+
+// Stamina per second
+const STAMINA_DRAIN: f32 = 20.;
+const STAMINA_RECOVERY: f32 = 12.5;
+// Fraction of stamina needed to sprint again after running out
+const EXHAUSTION_RECOVERY: f32 = 0.3;
+
+// Seconds a battery keeps the flashlight on
+const BATTERY_LIFE: f32 = 45.;
+// Seconds of charge left on the last battery when the flashlight starts to flicker
+const LOW_CHARGE: f32 = 10.;
+const FLASHLIGHT_RANGE: f32 = 8.;
+const FLASHLIGHT_ANGLE: f32 = 0.35;
+const FLASHLIGHT_INTENSITY: f32 = 4.;
 
 pub enum Action {
     Interact,
     Attack,
+    ToggleFlashlight,
 }
 
 pub struct SendAction {
@@ -25,7 +42,12 @@ pub struct SendAction {
 
 pub struct FlashLight {
     pub intesity: f32,
-    pub duration: u32,
+    pub duration: f32, // Seconds to fade back to normal over
+}
+
+/// Shakes the screen, `trauma` from 0 to 1 adds up with any shake already going on
+pub struct ShakeScreen {
+    pub trauma: f32,
 }
 
 pub enum ExitCondition {
@@ -33,23 +55,28 @@ pub enum ExitCondition {
     Lose,
 }
 
-struct Interact {
-    entity: Entity,
+pub struct Interact {
+    pub entity: Entity,
 }
 
 pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
     add_event::<SendAction>(world, schedule);
     add_event::<FlashLight>(world, schedule);
+    add_event::<ShakeScreen>(world, schedule);
     add_event::<ExitCondition>(world, schedule);
     add_event::<Interact>(world, schedule);
 
     schedule.add_systems((
         cam_follow_player,
+        apply_move_mode,
         turn_on_gen,
         use_light,
+        toggle_flashlight,
+        drain_flashlight.after(toggle_flashlight),
         pickup_battery,
         play_gen_sound,
         exit_door,
+        scripted_triggers,
         exit_on_dead,
         interact.in_base_set(CoreSet::First),
         despawn_interactable.in_base_set(CoreSet::Last),
@@ -75,6 +102,35 @@ fn interact(
         }
     }
 }
+fn apply_move_mode(
+    time: Res<Time>,
+    mut query: Query<(
+        &mut components::Player,
+        &mut components::Stamina,
+        &mut components::Movement,
+    )>,
+) {
+    for (mut player, mut stamina, mut movement) in query.iter_mut() {
+        let moving = movement.velocity() != Vec2::ZERO;
+
+        if player.mode == components::MoveMode::Sprint && (stamina.exhausted || !moving) {
+            player.mode = components::MoveMode::Walk;
+        }
+
+        if player.mode == components::MoveMode::Sprint {
+            stamina.current = (stamina.current - STAMINA_DRAIN * time.delta()).max(0.);
+            stamina.exhausted = stamina.current == 0.;
+        } else {
+            stamina.current = (stamina.current + STAMINA_RECOVERY * time.delta()).min(stamina.max);
+            if stamina.current >= stamina.max * EXHAUSTION_RECOVERY {
+                stamina.exhausted = false;
+            }
+        }
+
+        movement.set_speed(player.mode.speed());
+    }
+}
+
 fn cam_follow_player(
     mut cam: ResMut<Camera>,
     query: Query<&components::Transform, With<components::Player>>,
@@ -138,7 +194,7 @@ fn use_light(
 
         event_writer.send(FlashLight {
             intesity: 7.,
-            duration: (FPS as f32 * 0.5) as u32,
+            duration: 0.5,
         });
 
         sounds.push(
@@ -151,26 +207,103 @@ fn use_light(
     }
 }
 
+/// Cone a flashlight lights up when pointed from `pos` towards `dir`
+pub fn flashlight_cone(
+    pos: Vec2,
+    dir: Vec2,
+    flashlight: &components::Flashlight,
+) -> Option<Spotlight> {
+    if !flashlight.on || flashlight.brightness <= 0. {
+        return None;
+    }
+
+    Some(Spotlight {
+        pos,
+        dir,
+        range: FLASHLIGHT_RANGE,
+        angle: FLASHLIGHT_ANGLE,
+        intensity: FLASHLIGHT_INTENSITY * flashlight.brightness,
+    })
+}
+
+fn toggle_flashlight(
+    mut sounds: ResMut<sound::SoundQueue>,
+    mut event_reader: EventReader<SendAction>,
+    mut query: Query<(&components::Player, &mut components::Flashlight)>,
+) {
+    for event in event_reader.iter() {
+        let Action::ToggleFlashlight = event.action else {
+            continue;
+        };
+        let Ok((player, mut flashlight)) = query.get_mut(event.entity) else {
+            continue;
+        };
+
+        // Nothing to power it with
+        flashlight.on = !flashlight.on && (flashlight.charge > 0. || player.batteries > 0);
+
+        sounds.push(
+            sound::Track::Sfx,
+            sound::SoundInfo {
+                path: "click.wav".into(),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn drain_flashlight(
+    time: Res<Time>,
+    mut query: Query<(&mut components::Player, &mut components::Flashlight)>,
+) {
+    for (mut player, mut flashlight) in query.iter_mut() {
+        if !flashlight.on {
+            continue;
+        }
+
+        flashlight.charge -= time.delta();
+        if flashlight.charge <= 0. {
+            if player.batteries == 0 {
+                flashlight.on = false;
+                flashlight.charge = 0.;
+                continue;
+            }
+
+            player.batteries -= 1;
+            flashlight.charge += BATTERY_LIFE;
+        }
+
+        // Sputter more and more as the last battery dies
+        let flicker = if player.batteries == 0 && flashlight.charge < LOW_CHARGE {
+            1. - flashlight.charge / LOW_CHARGE
+        } else {
+            0.
+        };
+        flashlight.brightness = lighting::flicker(flicker, Vec2::ZERO, time.elapsed());
+    }
+}
+
 fn play_gen_sound(
+    time: Res<Time>,
     mut sounds: ResMut<sound::SoundQueue>,
     cam: Res<Camera>,
     gen_query: Query<(Entity, &components::Transform, &components::Generator)>,
-    mut sound_ticks: Local<HashMap<Entity, u32>>,
+    mut sound_timers: Local<HashMap<Entity, f32>>,
 ) {
-    let ticks_til_start = ticks(1.75);
+    let seconds_til_start = 1.75;
 
     for (ent, trans, gen) in gen_query.iter() {
         if !gen.is_on {
             continue;
         }
 
-        let Some(start) = sound_ticks.get_mut(&ent) else {
-            sound_ticks.insert(ent, ticks_til_start);
+        let Some(start) = sound_timers.get_mut(&ent) else {
+            sound_timers.insert(ent, seconds_til_start);
             continue;
         };
 
-        if *start != 0 {
-            *start -= 1;
+        if *start > 0. {
+            *start -= time.delta();
             continue;
         }
 
@@ -181,9 +314,20 @@ fn play_gen_sound(
     }
 }
 
+/// Light a generator gives off once it's running
+const GENERATOR_LIGHT: components::Light = components::Light {
+    radius: 5.,
+    intensity: 3.,
+    flicker: 0.,
+};
+
+#[allow(clippy::too_many_arguments)]
 fn turn_on_gen(
+    mut cmd: Commands,
     mut int_reader: EventReader<Interact>,
     mut light_writer: EventWriter<FlashLight>,
+    mut shake_writer: EventWriter<ShakeScreen>,
+    mut summon_writer: EventWriter<ai::SummonMonster>,
     mut data: ResMut<GameData>,
     mut sounds: ResMut<sound::SoundQueue>,
     cam: Res<Camera>,
@@ -201,13 +345,19 @@ fn turn_on_gen(
         sounds.push(sound::Track::Sfx, snd);
 
         gen.is_on = true;
+        cmd.entity(event.entity).insert(GENERATOR_LIGHT);
         data.generators_required -= 1;
 
+        // The noise draws another monster out
+        summon_writer.send(ai::SummonMonster);
+        shake_writer.send(ShakeScreen { trauma: 0.4 });
+
         if data.generators_required == 0 {
             light_writer.send(FlashLight {
                 intesity: f32::MAX,
-                duration: u32::MAX,
+                duration: f32::MAX,
             });
+            shake_writer.send(ShakeScreen { trauma: 0.6 });
 
             let snd = sound::SoundInfo {
                 path: "power_on.wav".into(),
@@ -221,6 +371,7 @@ fn turn_on_gen(
 
 fn pickup_battery(
     mut int_reader: EventReader<Interact>,
+    mut toast_writer: EventWriter<hud::Toast>,
     mut player_query: Query<&mut components::Player>,
     bat_query: Query<&components::Battery>,
 ) {
@@ -231,6 +382,12 @@ fn pickup_battery(
         };
 
             player.batteries += bat.amount;
+
+            let toast = match bat.amount {
+                1 => "Picked up a battery".to_string(),
+                amount => format!("Picked up {amount} batteries"),
+            };
+            toast_writer.send(hud::Toast(toast));
         }
     }
 }
@@ -249,21 +406,69 @@ fn despawn_interactable(
 
 fn exit_door(
     mut event_writer: EventWriter<ExitCondition>,
+    mut trigger_reader: EventReader<physics::TriggerEvent>,
     data: Res<GameData>,
-    query: Query<&components::Transform, With<components::Player>>,
-    exit_query: Query<&components::Transform, With<components::Exit>>,
+    query: Query<(), With<components::Player>>,
+    mut exit_query: Query<&mut components::Exit>,
 ) {
-    for trans_a in query.iter() {
+    for event in trigger_reader.iter() {
         if data.generators_required != 0 {
             continue;
         }
+        if let physics::TriggerState::Exit = event.state {
+            continue;
+        }
 
-        // power is on check if next to an exit
-        for trans_b in exit_query.iter() {
-            if trans_a.pos.distance_squared(trans_b.pos) > 1. {
-                continue;
+        // power is on check if the player is standing at an exit
+        if !query.contains(event.entity) {
+            continue;
+        }
+        let Ok(mut exit) = exit_query.get_mut(event.trigger) else {
+            continue;
+        };
+        if !exit.taken {
+            event_writer.send(ExitCondition::Win);
+            exit.taken = true;
+        }
+    }
+}
+
+fn scripted_triggers(
+    mut cmd: Commands,
+    mut trigger_reader: EventReader<physics::TriggerEvent>,
+    mut sounds: ResMut<sound::SoundQueue>,
+    mut toast_writer: EventWriter<hud::Toast>,
+    mut shake_writer: EventWriter<ShakeScreen>,
+    query: Query<(), With<components::Player>>,
+    scripted_query: Query<&components::Scripted>,
+) {
+    for event in trigger_reader.iter() {
+        let physics::TriggerState::Enter = event.state else {
+            continue;
+        };
+        if !query.contains(event.entity) {
+            continue;
+        }
+        let Ok(scripted) = scripted_query.get(event.trigger) else {
+            continue;
+        };
+
+        match &scripted.action {
+            components::TriggerAction::Sound(path) => sounds.push(
+                sound::Track::Sfx,
+                sound::SoundInfo {
+                    path: path.into(),
+                    ..Default::default()
+                },
+            ),
+            components::TriggerAction::Message(text) => toast_writer.send(hud::Toast(text.clone())),
+            components::TriggerAction::Shake(trauma) => {
+                shake_writer.send(ShakeScreen { trauma: *trauma })
             }
-            event_writer.send(ExitCondition::Win)
+        }
+
+        if scripted.once {
+            cmd.entity(event.trigger).despawn();
         }
     }
 }
@@ -0,0 +1,221 @@
+use crate::{graphics::Color, prelude::*};
+
+/// How much trauma the screen shake loses per second
+const SHAKE_DECAY: f32 = 1.5;
+
+/// 4x4 Bayer matrix used for ordered dithering
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Effects applied to a finished frame, set per level in `assets/rooms.ron`.
+/// Every pass is skipped while it's set to 0
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PostProcess {
+    /// Pixels the frame moves by at full screen shake
+    pub shake: f32,
+    /// Pixels the red and blue channels are pulled apart by at the edges of the screen
+    pub aberration: f32,
+    /// How much darker the corners get, from 0 to 1
+    pub vignette: f32,
+    /// How much darker every other row gets, from 0 to 1
+    pub scanlines: f32,
+    /// Strength of the noise that changes every frame, in colour levels
+    pub grain: f32,
+    /// Strength of the ordered dither pattern, in colour levels. Hides banding from the palette
+    pub dither: f32,
+    /// Colours every pixel gets snapped to, empty to keep full colour
+    pub palette: Vec<Color>,
+}
+
+impl PostProcess {
+    /// Runs every pass over an RGBA `frame` that's `width` pixels wide.
+    /// `seed` should change every frame so the grain moves
+    pub fn apply(&self, frame: &mut [u8], width: usize, shake: &Shake, seed: u32) {
+        let height = frame.len() / 4 / width;
+
+        let offset = shake.offset(self.shake);
+        if offset != IVec2::ZERO {
+            self.shift(frame, width, height, offset);
+        }
+        if self.aberration > 0. {
+            self.split_channels(frame, width);
+        }
+
+        let center = vec2(width as f32, height as f32) / 2.;
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (i % width, i / width);
+            let mut rgb = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+            let mut scale = 1.;
+
+            if self.vignette > 0. {
+                // 0 in the middle and 1 in the corners
+                let edge =
+                    ((vec2(x as f32, y as f32) + 0.5 - center) / center).length_squared() / 2.;
+                scale *= 1. - self.vignette * edge;
+            }
+            if self.scanlines > 0. && y % 2 == 1 {
+                scale *= 1. - self.scanlines;
+            }
+
+            let mut noise = 0.;
+            if self.grain > 0. {
+                noise += self.grain * (hash(x as u32, y as u32, seed) * 2. - 1.);
+            }
+            if self.dither > 0. {
+                let threshold = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16. - 0.5;
+                noise += self.dither * threshold;
+            }
+
+            for channel in &mut rgb {
+                *channel = *channel * scale + noise;
+            }
+
+            let rgb = if self.palette.is_empty() {
+                rgb
+            } else {
+                self.nearest(rgb)
+            };
+            for (out, channel) in pixel.iter_mut().zip(rgb) {
+                *out = channel.clamp(0., 255.) as u8;
+            }
+        }
+    }
+
+    /// Moves the whole frame, repeating the edge into the gap it leaves behind
+    fn shift(&self, frame: &mut [u8], width: usize, height: usize, offset: IVec2) {
+        let src = frame.to_vec();
+        for y in 0..height {
+            let src_y = (y as i32 - offset.y).clamp(0, height as i32 - 1) as usize;
+            for x in 0..width {
+                let src_x = (x as i32 - offset.x).clamp(0, width as i32 - 1) as usize;
+                let (i, j) = ((x + y * width) * 4, (src_x + src_y * width) * 4);
+                frame[i..i + 4].copy_from_slice(&src[j..j + 4]);
+            }
+        }
+    }
+
+    /// Pulls red outwards and blue inwards, more so towards the edges like a cheap lens
+    fn split_channels(&self, frame: &mut [u8], width: usize) {
+        let src = frame.to_vec();
+        let center = width as f32 / 2.;
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let (x, row) = (i % width, i - i % width);
+            let shift = (self.aberration * (x as f32 - center) / center) as i32;
+            let sample = |dx: i32, channel: usize| {
+                let x = (x as i32 - dx).clamp(0, width as i32 - 1) as usize;
+                src[(row + x) * 4 + channel]
+            };
+            pixel[0] = sample(shift, 0);
+            pixel[2] = sample(-shift, 2);
+        }
+    }
+
+    fn nearest(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let dist = |color: &Color| {
+            let [r, g, b, _] = color.slice();
+            (r as f32 - rgb[0]).powi(2) + (g as f32 - rgb[1]).powi(2) + (b as f32 - rgb[2]).powi(2)
+        };
+        self.palette
+            .iter()
+            .min_by(|a, b| dist(a).total_cmp(&dist(b)))
+            .map_or(rgb, |color| {
+                [color.r as f32, color.g as f32, color.b as f32]
+            })
+    }
+}
+
+/// Screen shake that builds up from gameplay events and settles down over time
+#[derive(Default)]
+pub struct Shake {
+    /// From 0 to 1, the shake grows with its square so small bumps stay subtle
+    trauma: f32,
+    time: f32,
+}
+
+impl Shake {
+    pub fn add(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.);
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.trauma = (self.trauma - SHAKE_DECAY * delta).max(0.);
+        self.time += delta;
+    }
+
+    /// How far to move the frame, up to `max` pixels
+    pub fn offset(&self, max: f32) -> IVec2 {
+        if max <= 0. {
+            return IVec2::ZERO;
+        }
+        (self.wobble() * max).round().as_ivec2()
+    }
+
+    /// Where the shake is right now, each axis from -1 to 1
+    pub fn wobble(&self) -> Vec2 {
+        if self.trauma <= 0. {
+            return Vec2::ZERO;
+        }
+
+        let t = self.time * 30.;
+        let wobble = vec2(
+            (t * 1.3).sin() * (t * 0.7).cos(),
+            (t * 1.1).cos() * (t * 0.9).sin(),
+        );
+        wobble * self.trauma * self.trauma
+    }
+}
+
+/// Cheap noise from 0 to 1 that's different for every pixel and seed
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    let mut h = x.wrapping_mul(374_761_393)
+        ^ y.wrapping_mul(668_265_263)
+        ^ seed.wrapping_mul(2_246_822_519);
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 7 % 256) as u8, (i * 3 % 256) as u8, 128, 255])
+            .collect()
+    }
+
+    #[test]
+    fn does_nothing_by_default() {
+        let mut frame = gradient(16, 8);
+        let before = frame.clone();
+        let mut shake = Shake::default();
+        shake.add(1.);
+        shake.update(0.1);
+
+        PostProcess::default().apply(&mut frame, 16, &shake, 1);
+        assert_eq!(frame, before);
+    }
+
+    #[test]
+    fn snaps_to_palette() {
+        let post = PostProcess {
+            palette: vec![Color::from_rgb(0, 0, 0), Color::from_rgb(255, 0, 0)],
+            ..Default::default()
+        };
+        let mut frame = vec![200, 40, 30, 255, 20, 30, 10, 255];
+        post.apply(&mut frame, 2, &Shake::default(), 0);
+        assert_eq!(frame, [255, 0, 0, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn shake_settles_down() {
+        let mut shake = Shake::default();
+        shake.add(0.8);
+        shake.update(0.05);
+        assert_ne!(shake.offset(8.), IVec2::ZERO);
+
+        shake.update(1.);
+        assert_eq!(shake.offset(8.), IVec2::ZERO);
+    }
+}
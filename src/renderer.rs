@@ -0,0 +1,911 @@
+use rayon::prelude::*;
+
+use crate::{
+    graphics::{Color, Texture, TextureRegistry},
+    idx,
+    lighting::{Fog, LightMap, Spotlight},
+    map,
+    prelude::*,
+    state::game::Camera,
+};
+
+pub struct Lighting<'a> {
+    /// Brightness multiplier, 1 being normal
+    pub intensity: f32,
+    /// How quickly things fade into the fog with distance
+    pub darkness: f32,
+    pub fog: Fog,
+    /// Light from light sources, added on top of `intensity`
+    pub lightmap: Option<&'a LightMap>,
+    pub flashlight: Option<Spotlight>,
+}
+
+impl Lighting<'_> {
+    /// Brightness at a point in the map
+    fn intensity_at(&self, pos: Vec2) -> f32 {
+        self.intensity
+            + self.lightmap.map_or(0., |lightmap| lightmap.level(pos))
+            + self.flashlight.map_or(0., |spot| spot.level(pos))
+    }
+
+    /// Fades a colour into the fog. `dist` is how far away it is, scaled so that
+    /// anything closer than 1 is fully visible at normal intensity
+    fn shade(&self, rgba: [u8; 4], brightness: f32, dist: f32, intensity: f32) -> [u8; 4] {
+        let visibility = (intensity / (dist * self.darkness)).clamp(0., 1.);
+        let visibility = visibility.powf(self.fog.curve);
+        let fog = self.fog.color.slice();
+
+        let mut out = rgba;
+        for i in 0..3 {
+            let color = rgba[i] as f32 * brightness;
+            out[i] = (fog[i] as f32 + (color - fog[i] as f32) * visibility) as u8;
+        }
+        out
+    }
+}
+
+impl Default for Lighting<'_> {
+    fn default() -> Self {
+        Self {
+            intensity: 1.,
+            darkness: 3.5,
+            fog: Fog::default(),
+            lightmap: None,
+            flashlight: None,
+        }
+    }
+}
+
+/// How much shorter than wide everything is drawn. The raycaster has always made walls a screen
+/// tall at a distance of 1 while fitting a camera plane of 0.66 across a 16:9 screen
+const SQUASH: f32 = 0.66 * 2. * 9. / 16.;
+
+/// Software raycaster that draws a map, as seen from a camera, into an RGBA buffer.
+/// Doesn't need a window so it can render offscreen as well
+pub struct Renderer {
+    width: usize,
+    height: usize,
+    z_buffer: Vec<f32>,
+    /// The frame stored column by column, so every column can be drawn on its own thread
+    columns: Vec<u8>,
+    /// Draw far away walls and floors from smaller copies of their textures, which stops them shimmering
+    pub mipmaps: bool,
+}
+
+impl Renderer {
+    /// Textures the renderer looks up by itself, which should be registered up front
+    pub const TEXTURES: [&'static str; 5] = ["floor", "ceil", "wall", "exit", "sky"];
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            z_buffer: vec![0.; width],
+            columns: vec![0; width * height * 4],
+            mipmaps: false,
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Changes the size of the frames drawn, keeping the other settings
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Self {
+            mipmaps: self.mipmaps,
+            ..Self::new(width, height)
+        };
+    }
+
+    /// Draws into `frame`, which has to hold `width * height` RGBA pixels.
+    /// Columns are spread over rayon's thread pool, the output doesn't depend on how many threads there are
+    pub fn render(
+        &mut self,
+        frame: &mut [u8],
+        map: &map::Map,
+        cam: &Camera,
+        sprites: &[(components::Transform, &components::Sprite)],
+        textures: &TextureRegistry,
+        light: &Lighting,
+    ) {
+        let (width, height) = (self.width, self.height);
+        assert_eq!(
+            frame.len(),
+            width * height * 4,
+            "frame doesn't match the renderer size"
+        );
+
+        // followed this tutorial lmao https://lodev.org/cgtutor/raycasting.html
+        let scene = Scene {
+            width,
+            height,
+            scale: self.scale(cam),
+            horizon: self.horizon(cam),
+            map,
+            cam,
+            light,
+            floor: self.floor_rows(cam),
+            looks: map
+                .looks()
+                .iter()
+                .map(|look| LookTextures {
+                    floor: texture(textures, &look.floor),
+                    ceiling: look.ceiling.as_ref().map(|name| texture(textures, name)),
+                })
+                .collect(),
+            sky: texture(textures, "sky"),
+            wall: texture(textures, "wall"),
+            exit: texture(textures, "exit"),
+            sprites: self.project_sprites(cam, sprites, textures, light),
+            mipmaps: self.mipmaps,
+        };
+
+        // Every column only touches its own pixels and z-buffer entry
+        self.columns
+            .par_chunks_mut(height * 4)
+            .zip(self.z_buffer.par_iter_mut())
+            .enumerate()
+            .for_each(|(x, (column, z))| {
+                scene.draw_floor(x, column);
+                *z = scene.draw_wall(x, column);
+                scene.draw_sprites(x, column, *z);
+            });
+
+        // Turn the columns back into rows
+        let columns = &self.columns;
+        frame
+            .par_chunks_mut(width * 4)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    let i = (x * height + y) * 4;
+                    pixel.copy_from_slice(&columns[i..i + 4]);
+                }
+            });
+    }
+
+    /// How many pixels tall something 1 unit high is at a distance of 1.
+    /// Worked out from the camera plane so the view looks the same at any resolution and
+    /// aspect ratio, and zooms evenly with the field of view
+    fn scale(&self, cam: &Camera) -> f32 {
+        self.width as f32 / 2. / cam.plane.length() * SQUASH
+    }
+
+    /// Screen row level with the camera's eyes, moved by looking up and down
+    fn horizon(&self, cam: &Camera) -> i32 {
+        self.height as i32 / 2 + (cam.pitch * self.height as f32).round() as i32
+    }
+
+    /// Works out how far away the floor or ceiling seen through every row of the screen is
+    fn floor_rows(&self, cam: &Camera) -> Vec<FloorRow> {
+        let (width, height) = (self.width, self.height);
+        let ray_0 = cam.dir - cam.plane;
+        let ray_1 = cam.dir + cam.plane;
+        let horizon = self.horizon(cam);
+
+        (0..height as i32)
+            .map(|y| {
+                // Rows above the horizon see the ceiling, the same way rows below see the floor
+                let ceiling = y < horizon;
+                let (cur_y_pos, vertical_pos) = if ceiling {
+                    (horizon - y - 1, 0.5 - cam.z)
+                } else {
+                    (y - horizon, 0.5 + cam.z)
+                };
+
+                let dist = vertical_pos.max(0.) * self.scale(cam) / cur_y_pos as f32;
+                let step = dist * (ray_1 - ray_0) / width as f32;
+
+                FloorRow {
+                    dist,
+                    ceiling,
+                    step: step.length(),
+                }
+            })
+            .collect()
+    }
+
+    /// Works out where every sprite lands on screen, sorted far to close
+    fn project_sprites<'a>(
+        &self,
+        cam: &Camera,
+        sprites: &[(components::Transform, &components::Sprite)],
+        textures: &'a TextureRegistry,
+        light: &Lighting,
+    ) -> Vec<ProjectedSprite<'a>> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let scale = self.scale(cam);
+
+        let mut sprites: Vec<&(components::Transform, &components::Sprite)> =
+            sprites.iter().collect();
+        sprites.sort_by(|(trans_a, _), (trans_b, _)| {
+            // Sort based on the sprite's distance to camera (far to close)
+            let dist_a = trans_a.pos.distance_squared(cam.pos);
+            dist_a
+                .total_cmp(&trans_b.pos.distance_squared(cam.pos))
+                .reverse()
+        });
+
+        sprites
+            .into_iter()
+            .filter_map(|(trans, sprite)| {
+                let tex = sprite.texture.and_then(|id| textures.get(id))?;
+                let region = sprite.region.unwrap_or(components::Region {
+                    pos: UVec2::ZERO,
+                    size: uvec2(tex.width(), tex.height()),
+                });
+
+                // sprite position relative to camera
+                let pos = trans.pos - cam.pos;
+                let inverse = 1. / (cam.plane.x * cam.dir.y - cam.dir.x * cam.plane.y);
+                let trans_x = inverse * (cam.dir.y * pos.x - cam.dir.x * pos.y);
+                let trans_y = inverse * (-cam.plane.y * pos.x + cam.plane.x * pos.y);
+
+                // Prevent number from being too low
+                if trans_y.abs() < 0.001 {
+                    return None;
+                }
+
+                // Sprites sit lower on screen the higher up the camera is
+                let move_screen = ((-sprite.height + cam.z * scale) / trans_y) as i32
+                    + self.horizon(cam)
+                    - height / 2;
+
+                let screen_x = ((width as f32 / 2.) * (1. + trans_x / trans_y)) as i32;
+                let sprite_height = (scale / trans_y * trans.scale.y).abs() as i32;
+                let sprite_width = (scale / trans_y * trans.scale.x).abs() as i32;
+
+                let draw_start = uvec2(
+                    (-sprite_width / 2 + screen_x).max(0) as u32,
+                    (-sprite_height / 2 + height / 2 + move_screen).max(0) as u32,
+                );
+                let draw_end = uvec2(
+                    (sprite_width / 2 + screen_x).clamp(0, width) as u32,
+                    (sprite_height / 2 + height / 2 + move_screen).clamp(0, height) as u32,
+                );
+
+                Some(ProjectedSprite {
+                    tex,
+                    region,
+                    depth: trans_y,
+                    left: -sprite_width / 2 + screen_x,
+                    size: ivec2(sprite_width, sprite_height),
+                    move_screen,
+                    draw_start,
+                    draw_end,
+                    dist: trans.pos.distance(cam.pos) / 2.,
+                    intensity: light.intensity_at(trans.pos),
+                })
+            })
+            .collect()
+    }
+}
+
+/// How far away the floor or ceiling seen through one row of the screen is
+struct FloorRow {
+    dist: f32,
+    ceiling: bool,
+    /// Tiles between neighbouring pixels, to pick a mipmap that doesn't shimmer
+    step: f32,
+}
+
+/// Textures for a `map::Look`
+struct LookTextures<'a> {
+    floor: Option<&'a Texture>,
+    /// `None` when the tile is open to the sky
+    ceiling: Option<Option<&'a Texture>>,
+}
+
+/// A sprite after being projected onto the screen
+struct ProjectedSprite<'a> {
+    tex: &'a Texture,
+    region: components::Region,
+    /// Distance in front of the camera plane
+    depth: f32,
+    /// Screen column of the sprite's left edge, which can be off screen
+    left: i32,
+    size: IVec2,
+    move_screen: i32,
+    draw_start: UVec2,
+    draw_end: UVec2,
+    dist: f32,
+    intensity: f32,
+}
+
+/// Everything needed to draw a frame, shared between the threads drawing its columns
+struct Scene<'a> {
+    width: usize,
+    height: usize,
+    /// See `Renderer::scale`
+    scale: f32,
+    /// See `Renderer::horizon`
+    horizon: i32,
+    map: &'a map::Map,
+    cam: &'a Camera,
+    light: &'a Lighting<'a>,
+    floor: Vec<FloorRow>,
+    /// Indexed the same as `map::Map::looks`
+    looks: Vec<LookTextures<'a>>,
+    /// Panorama seen above tiles without a ceiling, the fog colour is used if it's missing
+    sky: Option<&'a Texture>,
+    wall: Option<&'a Texture>,
+    exit: Option<&'a Texture>,
+    sprites: Vec<ProjectedSprite<'a>>,
+    mipmaps: bool,
+}
+
+impl Scene<'_> {
+    fn draw_floor(&self, x: usize, column: &mut [u8]) {
+        // Same ray as the wall in this column, so the floor lines up with it at any width
+        let cam_x = 2. * x as f32 / self.width as f32 - 1.;
+        let ray = self.cam.dir + self.cam.plane * cam_x;
+
+        // The sky wraps all the way around and reaches down to the horizon
+        let sky_u = ray.y.atan2(ray.x) / std::f32::consts::TAU;
+
+        for (y, (row, pixel)) in self
+            .floor
+            .iter()
+            .zip(column.chunks_exact_mut(4))
+            .enumerate()
+        {
+            let floor_pos = self.cam.pos + row.dist * ray;
+            let tile = floor_pos.floor();
+            let look = &self.looks[self.map.look_id(tile.x as i32, tile.y as i32)];
+
+            let tex = match (row.ceiling, look.ceiling) {
+                (false, _) => look.floor,
+                (true, Some(ceiling)) => ceiling,
+                (true, None) => {
+                    let rgba = match self.sky {
+                        // Fogged as if it were a ceiling up where the walls end, so it fades
+                        // towards the horizon and darkens with the rest of the level.
+                        // Lamps don't reach it, only the light everywhere does
+                        Some(sky) => {
+                            let v = y as f32 / self.horizon.max(1) as f32;
+                            let rgba = sky.sample(vec2(sky_u, v.min(0.999))).slice();
+                            self.light
+                                .shade(rgba, 1., row.dist / 0.5, self.light.intensity)
+                        }
+                        None => self.light.fog.color.slice(),
+                    };
+                    pixel.copy_from_slice(&rgba);
+                    continue;
+                }
+            };
+            let Some(tex) = tex else {
+                pixel.copy_from_slice(&self.light.fog.color.slice());
+                continue;
+            };
+            let tex = if self.mipmaps {
+                tex.mip(row.step * tex.width() as f32)
+            } else {
+                tex
+            };
+
+            // Position within the tile
+            let uv = floor_pos - tile;
+            let intensity = self.light.intensity_at(floor_pos);
+
+            let rgba = self
+                .light
+                .shade(tex.sample(uv).slice(), 0.5, row.dist / 0.5, intensity);
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// Draws the wall seen through column `x` and returns how far away it is
+    fn draw_wall(&self, x: usize, column: &mut [u8]) -> f32 {
+        let (width, height) = (self.width, self.height);
+
+        // cam coordinates in range of -1 to 1
+        let cam_x = 2. * x as f32 / width as f32 - 1.;
+        let ray = self.cam.dir + self.cam.plane * cam_x;
+
+        // Since the ray isn't normalized the distance is perpendicular to the camera plane,
+        // which avoids a fisheye effect
+        let Some(hit) = physics::raycast(self.map, self.cam.pos, ray, f32::MAX) else {
+            return f32::MAX;
+        };
+        let side = hit.side == physics::Side::Y;
+        let perp_wall_dist = hit.dist;
+
+        let wall_height = (self.scale / perp_wall_dist) as i32;
+        // Screen row of the middle of the wall
+        let middle = self.horizon + (self.cam.z * self.scale / perp_wall_dist) as i32;
+        let draw_start = (-wall_height / 2 + middle).max(0);
+        let draw_end = (wall_height / 2 + middle).min(height as i32);
+
+        // Walls are lit by the tile in front of them
+        let intensity = self.light.intensity_at(hit.point - ray.normalize() * 0.01);
+        let dist = self.scale / wall_height as f32;
+        // Shade one side darker so corners stand out
+        let brightness = if side { 0.5 } else { 1. };
+
+        let tile = self
+            .map
+            .get_tile(hit.tile.x as u32, hit.tile.y as u32)
+            .expect("tile should have been found already");
+        let tex = match tile {
+            map::Tile::Exit => self.exit,
+            _ => self.wall,
+        };
+        let Some(tex) = tex else {
+            warn!("Missing texture for {tile:?}");
+            return perp_wall_dist;
+        };
+        let tex = if self.mipmaps {
+            tex.mip(tex.height() as f32 / wall_height as f32)
+        } else {
+            tex
+        };
+
+        // texture stuff
+        let mut wall_x = if !side { hit.point.y } else { hit.point.x };
+        wall_x -= wall_x.floor();
+
+        // texture x coordinate
+        let mut tex_x = ((wall_x * tex.width() as f32) as u32).min(tex.width() - 1);
+        if (!side && ray.x > 0.) || (side && ray.y < 0.) {
+            tex_x = tex.width() - tex_x - 1;
+        }
+
+        let step = tex.height() as f32 / wall_height as f32;
+        let mut tex_pos = (draw_start - middle + wall_height / 2) as f32 * step;
+
+        for y in draw_start..draw_end {
+            let tex_y = tex_pos as u32 % tex.height();
+            tex_pos += step;
+
+            // Multiply tex coordinates by 4 to ensure index rgba is in correct order
+            let idx = idx(tex_x * 4, tex_y * 4, tex.width());
+            let rgba = self
+                .light
+                .shade(tex.pixel(idx).slice(), brightness, dist, intensity);
+
+            let i = y as usize * 4;
+            column[i..i + 4].copy_from_slice(&rgba);
+        }
+
+        perp_wall_dist
+    }
+
+    /// Draws the sprites in column `x` that are in front of the wall at `depth`
+    fn draw_sprites(&self, x: usize, column: &mut [u8], depth: f32) {
+        let height = self.height as i32;
+        let x = x as u32;
+
+        for sprite in &self.sprites {
+            if !(sprite.draw_start.x..sprite.draw_end.x).contains(&x) {
+                continue;
+            }
+            if !(sprite.depth > 0. && sprite.depth < depth) {
+                continue;
+            }
+
+            let (tex, region, size) = (sprite.tex, sprite.region, sprite.size);
+            let tex_x = (256 * (x as i32 - sprite.left) as u32 * region.size.x / size.x as u32)
+                / 256
+                + region.pos.x;
+
+            for y in sprite.draw_start.y..sprite.draw_end.y {
+                let d =
+                    ((y as i32 - sprite.move_screen) * 256 - height * 128 + size.y * 128) as u32;
+                let tex_y = (d * region.size.y) / size.y as u32 / 256 + region.pos.y;
+                let idx = idx(tex_x * 4, tex_y * 4, tex.width());
+                let color = tex.pixel(idx);
+
+                if color.a == 0 {
+                    continue;
+                }
+
+                let i = y as usize * 4;
+
+                let color = self
+                    .light
+                    .shade(color.slice(), 1., sprite.dist, sprite.intensity);
+                let mut prev_color = Color::from(&column[i..i + 4]);
+                prev_color.blend(Color::from(&color[..]));
+
+                column[i..i + 4].copy_from_slice(&prev_color.slice());
+            }
+        }
+    }
+}
+
+fn texture<'a>(textures: &'a TextureRegistry, name: &str) -> Option<&'a Texture> {
+    textures.find(name).and_then(|id| textures.get(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::{Look, Map, Tile},
+        screenshot,
+        settings::Display,
+    };
+    use std::path::PathBuf;
+
+    const WIDTH: usize = 160;
+    const HEIGHT: usize = 90;
+    /// Largest difference allowed in any colour channel before a pixel counts as changed
+    const CHANNEL_TOLERANCE: u8 = 8;
+    /// Fraction of pixels that may change before the test fails
+    const PIXEL_TOLERANCE: f32 = 0.005;
+
+    /// Builds a map from rows where `#` is a wall, `E` is an exit and anything else is empty
+    fn map(rows: &[&str]) -> Map {
+        let mut map = Map::new(rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let tile = match c {
+                    '#' => Tile::Wall,
+                    'E' => Tile::Exit,
+                    _ => continue,
+                };
+                map.set_tile(x as u32, y as u32, tile);
+            }
+        }
+        map
+    }
+
+    fn textures() -> TextureRegistry {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/textures");
+        let mut textures = TextureRegistry::default();
+        for name in Renderer::TEXTURES.into_iter().chain(["generator"]) {
+            let img = image::open(dir.join(format!("{name}.png")))
+                .unwrap_or_else(|err| panic!("failed to load texture {name}: {err}"));
+            textures.insert(name, Texture::from(img));
+        }
+        textures
+    }
+
+    fn test_map() -> Map {
+        map(&[
+            "########", "#------#", "#--#---E", "#------#", "#------#", "########",
+        ])
+    }
+
+    fn render_with(cam: &Camera, light: &Lighting, mipmaps: bool) -> Vec<u8> {
+        render_sized(cam, light, mipmaps, WIDTH, HEIGHT)
+    }
+
+    fn render_sized(
+        cam: &Camera,
+        light: &Lighting,
+        mipmaps: bool,
+        width: usize,
+        height: usize,
+    ) -> Vec<u8> {
+        let map = test_map();
+        let textures = textures();
+        let sprite = components::Sprite {
+            texture: textures.find("generator"),
+            ..Default::default()
+        };
+        let sprites = [(
+            components::Transform {
+                pos: vec2(4.5, 3.5),
+                ..Default::default()
+            },
+            &sprite,
+        )];
+
+        let mut frame = vec![0; width * height * 4];
+        let mut renderer = Renderer::new(width, height);
+        renderer.mipmaps = mipmaps;
+        renderer.render(&mut frame, &map, cam, &sprites, &textures, light);
+        frame
+    }
+
+    fn render(cam: &Camera, light: &Lighting) -> Vec<u8> {
+        render_with(cam, light, false)
+    }
+
+    /// Compares a frame against `tests/golden/<name>.png`.
+    /// Run with `BLESS=1` to write the frame as the new reference instead
+    fn assert_golden(name: &str, frame: &[u8]) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{name}.png"));
+
+        if std::env::var_os("BLESS").is_some() {
+            screenshot::save(&path, frame, WIDTH as u32, HEIGHT as u32).unwrap();
+            return;
+        }
+
+        let golden = image::open(&path)
+            .unwrap_or_else(|err| panic!("missing golden image {}: {err}", path.display()))
+            .into_rgba8();
+        assert_eq!(
+            golden.dimensions(),
+            (WIDTH as u32, HEIGHT as u32),
+            "golden image {name} has the wrong size"
+        );
+
+        let changed = golden
+            .as_raw()
+            .chunks_exact(4)
+            .zip(frame.chunks_exact(4))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(*b)
+                    .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        let ratio = changed as f32 / (WIDTH * HEIGHT) as f32;
+
+        assert!(
+            ratio <= PIXEL_TOLERANCE,
+            "{name} differs from its golden image in {changed} pixels ({:.2}%)",
+            ratio * 100.
+        );
+    }
+
+    fn camera(pos: Vec2, dir: Vec2) -> Camera {
+        camera_sized(pos, dir, WIDTH, HEIGHT)
+    }
+
+    fn camera_sized(pos: Vec2, dir: Vec2, width: usize, height: usize) -> Camera {
+        let mut cam = Camera {
+            pos,
+            dir,
+            ..Default::default()
+        };
+        cam.fit_plane(Display::default().plane(uvec2(width as u32, height as u32)));
+        cam
+    }
+
+    #[test]
+    fn room() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        assert_golden("room", &render(&cam, &Lighting::default()));
+    }
+
+    #[test]
+    fn room_dark() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting {
+            intensity: 0.5,
+            ..Default::default()
+        };
+        assert_golden("room_dark", &render(&cam, &light));
+    }
+
+    #[test]
+    fn room_lit() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let mut lightmap = LightMap::new(8, 6);
+        let lamp = components::Light {
+            radius: 4.,
+            intensity: 3.,
+            flicker: 0.,
+        };
+        lightmap.add(&test_map(), vec2(5.5, 2.5), &lamp, lamp.intensity);
+
+        let light = Lighting {
+            lightmap: Some(&lightmap),
+            ..Default::default()
+        };
+        assert_golden("room_lit", &render(&cam, &light));
+    }
+
+    #[test]
+    fn room_flashlight() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting {
+            flashlight: Some(Spotlight {
+                pos: cam.pos,
+                dir: cam.dir,
+                range: 8.,
+                angle: 0.35,
+                intensity: 4.,
+            }),
+            ..Default::default()
+        };
+        assert_golden("room_flashlight", &render(&cam, &light));
+    }
+
+    #[test]
+    fn room_fog() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting {
+            fog: Fog {
+                color: Color::from_rgb(40, 70, 20),
+                curve: 0.8,
+            },
+            ..Default::default()
+        };
+        assert_golden("room_fog", &render(&cam, &light));
+    }
+
+    #[test]
+    fn room_mipmaps() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        assert_golden(
+            "room_mipmaps",
+            &render_with(&cam, &Lighting::default(), true),
+        );
+    }
+
+    #[test]
+    fn room_wide_fov() {
+        let mut cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let display = Display {
+            fov: 70.,
+            ..Default::default()
+        };
+        cam.fit_plane(display.plane(uvec2(WIDTH as u32, HEIGHT as u32)));
+        assert_golden("room_wide_fov", &render(&cam, &Lighting::default()));
+    }
+
+    #[test]
+    fn exit_corner() {
+        let cam = camera(vec2(2.5, 1.5), vec2(1., 0.3).normalize());
+        assert_golden("exit_corner", &render(&cam, &Lighting::default()));
+    }
+
+    #[test]
+    fn same_frame_on_any_thread_count() {
+        let cam = camera(vec2(2.5, 1.5), vec2(1., 0.3).normalize());
+        let light = Lighting::default();
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(|| render_with(&cam, &light, true));
+        let many = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap()
+            .install(|| render_with(&cam, &light, true));
+        assert!(single == many, "frames drawn on 1 and 4 threads differ");
+    }
+
+    #[test]
+    fn wide_screens_arent_stretched() {
+        let (wide, narrow) = (WIDTH * 3 / 2, WIDTH);
+        let pos = vec2(1.5, 3.5);
+        let light = Lighting::default();
+        let wide_frame = render_sized(
+            &camera_sized(pos, Vec2::X, wide, HEIGHT),
+            &light,
+            false,
+            wide,
+            HEIGHT,
+        );
+        let narrow_frame = render(&camera(pos, Vec2::X), &light);
+
+        // The middle column looks straight ahead on both, so it should be the same
+        let column = |frame: &[u8], width: usize| -> Vec<u8> {
+            (0..HEIGHT)
+                .flat_map(|y| {
+                    let i = (width / 2 + y * width) * 4;
+                    frame[i..i + 4].to_vec()
+                })
+                .collect()
+        };
+        assert_eq!(column(&wide_frame, wide), column(&narrow_frame, narrow));
+    }
+
+    #[test]
+    fn looking_up_moves_everything_down() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting::default();
+        let level = render(&cam, &light);
+        let up = render(&Camera { pitch: 0.1, ..cam }, &light);
+
+        // Shearing the view shifts whole rows, without changing how they look
+        let shift = (0.1 * HEIGHT as f32) as usize;
+        let row = |frame: &[u8], y: usize| frame[y * WIDTH * 4..(y + 1) * WIDTH * 4].to_vec();
+        let changed = (0..HEIGHT - shift)
+            .filter(|y| row(&level, *y) != row(&up, y + shift))
+            .count();
+        assert_eq!(changed, 0, "rows changed after being shifted");
+    }
+
+    #[test]
+    fn tiles_have_their_own_floor_and_ceiling() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting::default();
+        let flipped = Look {
+            floor: "ceil".to_string(),
+            ceiling: Some("floor".to_string()),
+        };
+        let outside = Look {
+            ceiling: None,
+            ..Default::default()
+        };
+
+        let looked = |look: &Look| {
+            let mut map = test_map();
+            for y in 0..map.height() {
+                for x in 0..map.width() {
+                    map.set_look(x, y, look);
+                }
+            }
+            let mut frame = vec![0; WIDTH * HEIGHT * 4];
+            Renderer::new(WIDTH, HEIGHT).render(&mut frame, &map, &cam, &[], &textures(), &light);
+            frame
+        };
+        let plain = looked(&Look::default());
+        let flipped = looked(&flipped);
+        let outside = looked(&outside);
+
+        let row = |frame: &[u8], y: usize| frame[y * WIDTH * 4..(y + 1) * WIDTH * 4].to_vec();
+        let (top, bottom) = (0, HEIGHT - 1);
+        assert_ne!(row(&plain, bottom), row(&flipped, bottom));
+        assert_ne!(row(&plain, top), row(&flipped, top));
+        // Without a ceiling the sky shows, the floor stays the same
+        assert_ne!(row(&plain, top), row(&outside, top));
+        assert_eq!(row(&plain, bottom), row(&outside, bottom));
+    }
+
+    #[test]
+    fn sky_fades_into_the_dark() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let mut map = test_map();
+        let outside = Look {
+            ceiling: None,
+            ..Default::default()
+        };
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                map.set_look(x, y, &outside);
+            }
+        }
+
+        let render = |light: &Lighting| {
+            let mut frame = vec![0; WIDTH * HEIGHT * 4];
+            Renderer::new(WIDTH, HEIGHT).render(&mut frame, &map, &cam, &[], &textures(), light);
+            frame
+        };
+        let brightness = |frame: &[u8]| -> u32 {
+            frame[..WIDTH * HEIGHT / 2 * 4]
+                .chunks_exact(4)
+                .map(|rgba| rgba[..3].iter().map(|c| *c as u32).sum::<u32>())
+                .sum()
+        };
+
+        let lit = render(&Lighting::default());
+        let dim = render(&Lighting {
+            intensity: 0.5,
+            ..Default::default()
+        });
+        let foggy = render(&Lighting {
+            darkness: 7.,
+            ..Default::default()
+        });
+        assert!(brightness(&dim) < brightness(&lit));
+        assert!(brightness(&foggy) < brightness(&lit));
+    }
+
+    #[test]
+    fn missing_textures_are_fogged() {
+        let cam = camera(vec2(1.5, 3.5), Vec2::X);
+        let light = Lighting::default();
+        let textures = textures();
+        let mut renderer = Renderer::new(WIDTH, HEIGHT);
+        let mut frame = vec![0; WIDTH * HEIGHT * 4];
+        let mut map = test_map();
+        renderer.render(&mut frame, &map, &cam, &[], &textures, &light);
+
+        // Nothing from the frame before should be left behind where a texture is missing
+        let missing = Look {
+            floor: "missing".to_string(),
+            ..Default::default()
+        };
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                map.set_look(x, y, &missing);
+            }
+        }
+        renderer.render(&mut frame, &map, &cam, &[], &textures, &light);
+        let bottom = &frame[(HEIGHT - 1) * WIDTH * 4..];
+        assert!(bottom
+            .chunks_exact(4)
+            .all(|rgba| rgba == light.fog.color.slice()));
+    }
+}
@@ -0,0 +1,33 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use directories::ProjectDirs;
+use image::{ColorType, ImageResult};
+
+/// Folder screenshots are written to, inside the user's data dir
+pub fn dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", crate::TITLE).map(|dirs| dirs.data_dir().join("screenshots"))
+}
+
+/// Saves an RGBA frame as a PNG named after the current time and returns where it went
+pub fn take(frame: &[u8], width: u32, height: u32) -> ImageResult<PathBuf> {
+    let dir = dir().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no user data dir found")
+    })?;
+    std::fs::create_dir_all(&dir)?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("screenshot-{time}.png"));
+
+    save(&path, frame, width, height)?;
+    Ok(path)
+}
+
+pub fn save(path: &Path, frame: &[u8], width: u32, height: u32) -> ImageResult<()> {
+    image::save_buffer(path, frame, width, height, ColorType::Rgba8)
+}
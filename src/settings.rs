@@ -0,0 +1,104 @@
+use crate::prelude::*;
+
+/// How the game is drawn. Can be changed while it's running, the screen gets resized to match
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Display {
+    /// Rows of pixels drawn, which get scaled up to fill the window
+    pub height: u32,
+    /// Width divided by height, `None` to follow the window
+    pub aspect: Option<f32>,
+    /// Field of view in degrees, measured up and down. The horizontal one grows with the
+    /// aspect ratio, so wider screens see more instead of getting stretched
+    pub fov: f32,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self {
+            height: 216,
+            aspect: None,
+            fov: Self::DEFAULT_FOV,
+        }
+    }
+}
+
+impl Display {
+    pub const HEIGHTS: [u32; 4] = [144, 216, 288, 360];
+    pub const ASPECTS: [Option<f32>; 4] = [None, Some(4. / 3.), Some(16. / 9.), Some(21. / 9.)];
+    /// Gives the camera plane of 0.66 the game has always had on a 16:9 screen,
+    /// `2 * atan(0.66 * 9 / 16)`
+    pub const DEFAULT_FOV: f32 = 40.734887;
+    pub const MIN_FOV: f32 = 30.;
+    pub const MAX_FOV: f32 = 100.;
+
+    /// Internal resolution for a window of `window` size
+    pub fn size(&self, window: UVec2) -> UVec2 {
+        let aspect = self.aspect.unwrap_or_else(|| {
+            if window.x == 0 || window.y == 0 {
+                16. / 9.
+            } else {
+                window.x as f32 / window.y as f32
+            }
+        });
+        let width = (self.height as f32 * aspect).round().max(1.) as u32;
+        uvec2(width, self.height.max(1))
+    }
+
+    /// Length of the camera plane for a camera looking down a direction of length 1,
+    /// when drawing at `size`
+    pub fn plane(&self, size: UVec2) -> f32 {
+        let aspect = size.x as f32 / size.y as f32;
+        (self.fov.to_radians() / 2.).tan() * aspect
+    }
+
+    /// Steps to the next internal resolution, going back to the smallest after the largest
+    pub fn next_height(&mut self) {
+        let i = Self::HEIGHTS.iter().position(|h| *h == self.height);
+        self.height = Self::HEIGHTS[i.map_or(0, |i| (i + 1) % Self::HEIGHTS.len())];
+    }
+
+    pub fn next_aspect(&mut self) {
+        let i = Self::ASPECTS.iter().position(|a| *a == self.aspect);
+        self.aspect = Self::ASPECTS[i.map_or(0, |i| (i + 1) % Self::ASPECTS.len())];
+    }
+
+    pub fn change_fov(&mut self, degrees: f32) {
+        self.fov = (self.fov + degrees).clamp(Self::MIN_FOV, Self::MAX_FOV);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_window_aspect() {
+        let display = Display::default();
+        assert_eq!(display.size(uvec2(1920, 1080)), uvec2(384, 216));
+        assert_eq!(display.size(uvec2(3440, 1440)), uvec2(516, 216));
+
+        let fixed = Display {
+            aspect: Some(4. / 3.),
+            ..display
+        };
+        assert_eq!(fixed.size(uvec2(3440, 1440)), uvec2(288, 216));
+    }
+
+    #[test]
+    fn wider_screens_see_more() {
+        let display = Display::default();
+        let wide = display.plane(uvec2(516, 216));
+        let narrow = display.plane(uvec2(384, 216));
+        assert!(wide > narrow);
+
+        // A unit is as many pixels across on both, the wide one just shows more of them
+        let across = |size: UVec2| size.x as f32 / 2. / display.plane(size);
+        assert!((across(uvec2(516, 216)) - across(uvec2(384, 216))).abs() < 0.01);
+    }
+
+    #[test]
+    fn default_matches_classic_view() {
+        let plane = Display::default().plane(uvec2(384, 216));
+        assert!((plane - 0.66).abs() < 0.0005, "plane is {plane}");
+    }
+}
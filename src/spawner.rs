@@ -1,29 +1,114 @@
 use bevy_ecs::{prelude::Entity, system::Commands};
+use rand::seq::SliceRandom;
 
-use crate::{ai, prelude::*, ticks};
+use crate::{
+    ai, animation::Animator, astar, graphics::TextureRegistry, map::MapGenerator, prelude::*,
+};
 
 use components::*;
 
+pub struct SpawnRules {
+    /// Minimum walking distance in tiles from the player's spawn
+    pub min_distance: u32,
+    /// Amount spawned on the first floor
+    pub count: u32,
+    /// Extra amount spawned for every floor after the first
+    pub per_floor: u32,
+}
+
+impl SpawnRules {
+    pub fn count(&self, floor: u32) -> u32 {
+        self.count + self.per_floor * floor.saturating_sub(1)
+    }
+}
+
+pub const MONSTER_RULES: SpawnRules = SpawnRules {
+    min_distance: 20,
+    count: 1,
+    per_floor: 1,
+};
+
 pub fn spawn_player(cmd: &mut Commands, trans: Transform) -> Entity {
     cmd.spawn((
         trans,
-        Movement::with_speed(0.2),
+        PreviousTransform(trans),
+        Movement::with_speed(MoveMode::Walk.speed()),
         Player::default(),
-        Collider::default(),
+        Stamina::default(),
+        Flashlight::default(),
+        Collider {
+            size: Vec2::splat(0.5),
+            ..Default::default()
+        },
         MonsterTarget::default(),
     ))
     .id()
 }
 
-pub fn spawn_monster(cmd: &mut Commands, trans: Transform) -> Entity {
+pub fn spawn_monster(
+    cmd: &mut Commands,
+    textures: &mut TextureRegistry,
+    trans: Transform,
+) -> Entity {
     cmd.spawn((
         trans,
+        PreviousTransform(trans),
         Monster {
-            state: MonsterState::Rest(ticks(20.)),
+            state: MonsterState::Rest(20.),
             attack_time: ai::ATTACK_TIME,
         },
-        Movement::with_speed(0.125),
+        Movement::with_speed(ai::WALK_SPEED),
+        Collider {
+            size: Vec2::splat(0.5),
+            ..Default::default()
+        },
         Navigator::default(),
+        Sprite::default(),
+        Animator::new(&ai::MONSTER_WALK, textures),
     ))
     .id()
 }
+
+/// Picks where monsters start on a generated map.
+/// Spawn markers from the room prefabs are used first and any empty tile afterwards
+pub fn monster_spawns(gen: &MapGenerator, rules: &SpawnRules, floor: u32) -> Vec<Vec2> {
+    let count = rules.count(floor) as usize;
+    let dist = astar::distances(&gen.map, gen.spawn);
+    let far_enough = |pos: &UVec2| {
+        dist.get(&pos.as_ivec2())
+            .is_some_and(|dist| *dist >= rules.min_distance)
+    };
+
+    let mut rng = rand::thread_rng();
+
+    let mut markers: Vec<UVec2> = gen
+        .monster_spawns
+        .iter()
+        .copied()
+        .filter(far_enough)
+        .collect();
+    markers.shuffle(&mut rng);
+
+    let mut spawns: Vec<UVec2> = markers.into_iter().take(count).collect();
+
+    if spawns.len() < count {
+        let mut tiles: Vec<UVec2> = dist
+            .keys()
+            .map(|pos| pos.as_uvec2())
+            .filter(|pos| far_enough(pos) && !spawns.contains(pos))
+            .collect();
+        tiles.shuffle(&mut rng);
+
+        spawns.extend(tiles.into_iter().take(count - spawns.len()));
+    }
+
+    if spawns.len() < count {
+        warn!(
+            "Only found {} of {} monster spawns far enough from the player",
+            spawns.len(),
+            count
+        );
+    }
+
+    spawns.into_iter().map(|pos| pos.as_vec2() + 0.5).collect()
+}
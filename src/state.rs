@@ -2,6 +2,7 @@ use crate::Context;
 pub struct AppState(Vec<Box<dyn State>>);
 
 pub mod game;
+pub mod note;
 
 impl AppState {
     pub fn new(default_state: Box<dyn State>) -> Self {
@@ -16,6 +17,23 @@ impl AppState {
         self.0.pop().expect("Last state should never be popped off")
     }
 
+    /// Whether something is open on top of the first state
+    pub fn has_overlay(&self) -> bool {
+        self.0.len() > 1
+    }
+
+    /// Draws the top state, along with the ones under it if it only covers part of them
+    pub fn draw(&mut self, ctx: &mut Context, screen: &mut [u8]) {
+        let bottom = self
+            .0
+            .iter()
+            .rposition(|state| !state.transparent())
+            .unwrap_or(0);
+        for state in &mut self.0[bottom..] {
+            state.draw(ctx, screen);
+        }
+    }
+
     #[allow(clippy::borrowed_box)]
     pub fn peek(&mut self) -> &mut Box<dyn State> {
         self.0
@@ -28,4 +46,10 @@ pub trait State {
     fn update(&mut self, ctx: &mut Context);
 
     fn draw(&mut self, ctx: &mut Context, screen: &mut [u8]);
+
+    /// Overlays return true so the states under them are still drawn. Only the top state
+    /// gets updated either way, so whatever is underneath stays paused
+    fn transparent(&self) -> bool {
+        false
+    }
 }
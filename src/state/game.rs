@@ -1,26 +1,31 @@
 use crate::{
-    graphics::{self, Color, Texture},
-    idx,
+    camera::{self, CameraEffects, CameraShake, PreviousCameraEffects},
+    graphics::{ColorLut, Texture, TextureRegistry},
+    hud::{self, Hud, HudInfo},
     input::KeyCode,
+    lighting::{Atmosphere, LightMap},
     map,
+    notes::{Journal, Notes, ReadNote},
     player::{self, ExitCondition},
+    postprocess::Shake,
     prelude::*,
+    renderer::{Lighting, Renderer},
     sound, spawner,
-    state::State,
-    Context, HEIGHT, WIDTH,
+    state::{
+        note::{JournalView, NoteView},
+        State,
+    },
+    Context,
 };
 
 use assets_manager::{asset::Wav, BoxedError};
-use bevy_ecs::{prelude::*, system::SystemState};
+use bevy_ecs::{event::ManualEventReader, prelude::*, system::SystemState};
 use kira::{
     manager::error::AddSubTrackError,
     sound::static_sound::{StaticSoundData, StaticSoundSettings},
     track::{TrackBuilder, TrackHandle},
     LoopBehavior,
 };
-use rand::Rng;
-
-const DARKNESS: f32 = 3.5;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 #[system_set(base)]
@@ -48,6 +53,8 @@ impl CoreSet {
 pub struct GameData {
     pub generators_required: u32,
     pub notes_taken: u32,
+    /// How deep the player is, starting at 1. Deeper floors are harder
+    pub floor: u32,
 }
 
 impl Default for GameData {
@@ -55,15 +62,21 @@ impl Default for GameData {
         Self {
             generators_required: 3,
             notes_taken: 0,
+            floor: 1,
         }
     }
 }
 
-#[derive(Resource, Debug)]
+#[derive(Resource, Debug, Clone, Copy)]
 pub struct Camera {
     pub pos: Vec2,
     pub dir: Vec2,
+    /// Fitted to the display settings every update, see `Camera::fit_plane`
     pub plane: Vec2,
+    /// Vertical look, done by shearing the view. How many screen heights the horizon moves down
+    pub pitch: f32,
+    /// Eye height above the usual half a unit off the floor
+    pub z: f32,
 }
 
 impl Default for Camera {
@@ -71,19 +84,49 @@ impl Default for Camera {
         Self {
             pos: Vec2::ZERO,
             dir: Vec2::NEG_X,
-            plane: Vec2::new(0., 0.66),
+            plane: Vec2::ZERO,
+            pitch: 0.,
+            z: 0.,
         }
     }
 }
 
+impl Camera {
+    /// Points the camera plane across `dir` with a length of `plane`, see `settings::Display::plane`
+    pub fn fit_plane(&mut self, plane: f32) {
+        self.plane = self.dir.perp() * -plane;
+    }
+
+    pub fn lerp(&self, to: &Camera, alpha: f32) -> Camera {
+        Camera {
+            pos: self.pos.lerp(to.pos, alpha),
+            dir: self.dir.lerp(to.dir, alpha).normalize_or_zero() * to.dir.length(),
+            plane: self.plane.lerp(to.plane, alpha).normalize_or_zero() * to.plane.length(),
+            pitch: lerp(self.pitch, to.pitch, alpha),
+            z: lerp(self.z, to.z, alpha),
+        }
+    }
+}
+
+/// Camera from the previous update, used to smooth out drawing between updates
+#[derive(Resource, Debug, Default)]
+pub struct PreviousCamera(pub Camera);
+
 #[derive(Default)]
 pub struct Controls {
     pub x: f32,
     pub y: f32,
     pub left: f32,
     pub right: f32,
+    /// 1 looking up, -1 looking down
+    pub look: f32,
+    /// 1 leaning right, -1 leaning left
+    pub lean: f32,
     pub interact: bool,
     pub attack: bool,
+    pub sprint: bool,
+    pub crouch: bool,
+    pub flashlight: bool,
     pub pause: bool,
 }
 
@@ -91,31 +134,67 @@ pub struct InGame {
     audio_tracks: Vec<TrackHandle>,
     world: World,
     schedule: Schedule,
-    z_buffer: Vec<f32>,
+    renderer: Renderer,
     controls: Controls,
     light_intensity: f32,
-    light_duration: u32,
+    light_duration: f32,
+    exit_reader: ManualEventReader<ExitCondition>,
+    flash_reader: ManualEventReader<player::FlashLight>,
+    shake_reader: ManualEventReader<player::ShakeScreen>,
+    note_reader: ManualEventReader<ReadNote>,
+    shake: Shake,
+    /// Frames drawn so far, seeds the film grain
+    frames: u32,
+    /// Colour grading applied to every frame
+    grade: Option<ColorLut>,
 }
 
 impl InGame {
     pub fn new(ctx: &mut Context) -> Self {
         let mut world = World::default();
         world.insert_resource(Camera::default());
+        world.insert_resource(PreviousCamera::default());
         world.insert_resource(GameData::default());
+        world.init_resource::<TextureRegistry>();
+        world.insert_resource(Notes::load(&ctx.assets));
 
         let mut schedule = CoreSet::schedule();
 
+        crate::time::add_to_world(&mut schedule, &mut world);
         crate::physics::add_to_world(&mut schedule, &mut world);
         crate::ai::add_to_world(&mut schedule, &mut world);
+        crate::animation::add_to_world(&mut schedule);
+        crate::camera::add_to_world(&mut schedule, &mut world);
+        crate::lighting::add_to_world(&mut schedule, &mut world);
         crate::player::add_to_world(&mut schedule, &mut world);
+        crate::hud::add_to_world(&mut schedule, &mut world);
+        crate::notes::add_to_world(&mut schedule, &mut world);
 
         setup_map(&mut world);
 
-        let load_assets = || -> Result<(), BoxedError> {
-            ctx.assets.load::<Texture>("textures.wall")?;
-            ctx.assets.load::<Texture>("textures.floor")?;
-            ctx.assets.load::<Texture>("textures.ceil")?;
+        let grade = world
+            .resource::<Atmosphere>()
+            .grade
+            .as_ref()
+            .and_then(|name| {
+                let lut = ctx
+                    .assets
+                    .load::<Texture>(&format!("textures.{name}"))
+                    .ok()
+                    .and_then(|tex| ColorLut::from_texture(&tex.read()));
+                if lut.is_none() {
+                    warn!("Could not load colour grading LUT {name}");
+                }
+                lut
+            });
+
+        let mut textures = world.resource_mut::<TextureRegistry>();
+        for name in Renderer::TEXTURES {
+            textures.id(name);
+        }
+        textures.load(&ctx.assets);
 
+        let load_assets = || -> Result<(), BoxedError> {
             ctx.assets.load::<Wav>("sounds.step")?;
             Ok(())
         };
@@ -149,21 +228,46 @@ impl InGame {
             warn!("Bruh, audio tracks couldn't be set up properly. There goes the sound.");
         }
 
+        let mut renderer = Renderer::new(ctx.size.x as usize, ctx.size.y as usize);
+        renderer.mipmaps = true;
+
         Self {
             audio_tracks: tracks,
             world,
             schedule,
-            z_buffer: vec![0.; WIDTH],
+            renderer,
             controls: Default::default(),
             light_intensity: 1.,
-            light_duration: 0,
+            light_duration: 0.,
+            exit_reader: ManualEventReader::default(),
+            flash_reader: ManualEventReader::default(),
+            shake_reader: ManualEventReader::default(),
+            note_reader: ManualEventReader::default(),
+            shake: Shake::default(),
+            frames: 0,
+            grade,
         }
     }
 }
 
-const SENSITIVITY: f32 = 1. / FPS as f32 * 2.5;
+/// Floors to get through. Taking the exit on the last one wins
+const FLOORS: u32 = 3;
+// Radians turned per second
+const TURN_SPEED: f32 = 2.5;
+/// Screen heights the view shears per second when looking up or down
+const LOOK_SPEED: f32 = 0.6;
+/// Furthest up or down the view can shear before it looks too warped
+const MAX_PITCH: f32 = 0.3;
 impl State for InGame {
     fn update(&mut self, ctx: &mut Context) {
+        let cam = *self.world.resource::<Camera>();
+        self.world.resource_mut::<PreviousCamera>().0 = cam;
+        let effects = *self.world.resource::<CameraEffects>();
+        self.world.resource_mut::<PreviousCameraEffects>().0 = effects;
+        let delta = self.world.resource::<Time>().delta();
+        let floor = self.world.resource::<GameData>().floor;
+        let turn = TURN_SPEED * delta;
+
         self.controls = {
             let x = ctx.input.held(KeyCode::D) as i8 - ctx.input.held(KeyCode::A) as i8;
             let y = ctx.input.held(KeyCode::S) as i8 - ctx.input.held(KeyCode::W) as i8;
@@ -171,6 +275,8 @@ impl State for InGame {
                 ctx.input.held(KeyCode::Left) as i8 as f32,
                 ctx.input.held(KeyCode::Right) as i8 as f32,
             );
+            let look = ctx.input.held(KeyCode::Up) as i8 - ctx.input.held(KeyCode::Down) as i8;
+            let lean = ctx.input.held(KeyCode::R) as i8 - ctx.input.held(KeyCode::Q) as i8;
             // let (left, right) = self.input.mouse_diff();
 
             Controls {
@@ -178,50 +284,46 @@ impl State for InGame {
                 y: y as f32,
                 left,
                 right,
+                look: look as f32,
+                lean: lean as f32,
                 interact: ctx.input.pressed(KeyCode::E),
                 attack: ctx.input.pressed(KeyCode::Space),
+                sprint: ctx.input.held(KeyCode::LShift),
+                crouch: ctx.input.held(KeyCode::LControl) || ctx.input.held(KeyCode::C),
+                flashlight: ctx.input.pressed(KeyCode::F),
                 ..Default::default()
             }
         };
 
+        match take_exit(&self.world, &mut self.exit_reader, floor) {
+            Exit::Stay => {}
+            Exit::Descend => {
+                next_floor(&mut self.world);
+                return;
+            }
+            Exit::End(msg) => {
+                println!("{msg}");
+                ctx.request_exit();
+                return;
+            }
+        }
+
         #[allow(clippy::type_complexity)]
         let mut system_state: SystemState<(
             EventWriter<player::SendAction>,
-            EventReader<player::ExitCondition>,
             ResMut<Camera>,
-            Query<
-                (
-                    Entity,
-                    &mut components::Transform,
-                    &mut components::Movement,
-                ),
-                With<components::Player>,
-            >,
+            Query<(
+                Entity,
+                &mut components::Transform,
+                &mut components::Movement,
+                &mut components::Player,
+            )>,
         )> = SystemState::new(&mut self.world);
 
-        let (mut writer, mut exit_reader, mut cam, mut player_query) =
-            system_state.get_mut(&mut self.world);
-
-        let mut exit = false;
-        let mut msg = "";
-        for event in exit_reader.iter() {
-            msg = if let ExitCondition::Win = event {
-                "yay you win"
-            } else {
-                "you are ded. not big surprise"
-            };
-
-            exit = true;
-        }
-
-        if exit {
-            println!("{msg}");
-            ctx.request_exit();
-            return;
-        }
+        let (mut writer, mut cam, mut player_query) = system_state.get_mut(&mut self.world);
 
         // Input
-        for (ent, mut trans, mut movement) in player_query.iter_mut() {
+        for (ent, mut trans, mut movement, mut player) in player_query.iter_mut() {
             let mut vel = Vec2::ZERO;
 
             if self.controls.y < 0. {
@@ -239,25 +341,31 @@ impl State for InGame {
 
             movement.set_velocity(vel);
 
+            player.mode = if self.controls.crouch {
+                components::MoveMode::Crouch
+            } else if self.controls.sprint {
+                components::MoveMode::Sprint
+            } else {
+                components::MoveMode::Walk
+            };
+            player.lean = self.controls.lean;
+
+            cam.pitch =
+                (cam.pitch + self.controls.look * LOOK_SPEED * delta).clamp(-MAX_PITCH, MAX_PITCH);
+
             if self.controls.right != 0. {
-                let rot = -SENSITIVITY;
+                let rot = -turn;
                 let prev_dir_x = cam.dir.x;
-                let prev_plane_x = cam.plane.x;
 
                 cam.dir.x = cam.dir.x * rot.cos() - cam.dir.y * rot.sin();
                 cam.dir.y = prev_dir_x * rot.sin() + cam.dir.y * rot.cos();
-                cam.plane.x = cam.plane.x * rot.cos() - cam.plane.y * rot.sin();
-                cam.plane.y = prev_plane_x * rot.sin() + cam.plane.y * rot.cos();
             }
             if self.controls.left != 0. {
-                let rot = SENSITIVITY;
+                let rot = turn;
                 let prev_dir_x = cam.dir.x;
-                let prev_plane_x = cam.plane.x;
 
                 cam.dir.x = cam.dir.x * rot.cos() - cam.dir.y * rot.sin();
                 cam.dir.y = prev_dir_x * rot.sin() + cam.dir.y * rot.cos();
-                cam.plane.x = cam.plane.x * rot.cos() - cam.plane.y * rot.sin();
-                cam.plane.y = prev_plane_x * rot.sin() + cam.plane.y * rot.cos();
             }
 
             if self.controls.interact {
@@ -272,12 +380,39 @@ impl State for InGame {
                     action: player::Action::Attack,
                 });
             }
+            if self.controls.flashlight {
+                writer.send(player::SendAction {
+                    entity: ent,
+                    action: player::Action::ToggleFlashlight,
+                });
+            }
 
             trans.dir = cam.dir;
         }
+        cam.fit_plane(ctx.display.plane(ctx.size));
 
         self.schedule.run(&mut self.world);
 
+        // Reading a note or the journal pauses everything until it's closed
+        let notes = self.world.resource::<Notes>();
+        let read = self
+            .note_reader
+            .iter(self.world.resource::<Events<ReadNote>>())
+            .last();
+        let view: Option<Box<dyn State>> =
+            if let Some(note) = read.and_then(|ReadNote(id)| notes.0.get(*id)) {
+                Some(Box::new(NoteView::new(ctx, note.clone())))
+            } else if ctx.input.pressed(KeyCode::J) {
+                let journal = self.world.resource::<Journal>();
+                Some(Box::new(JournalView::new(notes, journal)))
+            } else {
+                None
+            };
+        if let Some(view) = view {
+            hold_still(&mut self.world);
+            ctx.push_state(view);
+        }
+
         // Play sounds
         let Some(mut sounds) = self.world.get_resource_mut::<sound::SoundQueue>() else {
             return;
@@ -310,289 +445,112 @@ impl State for InGame {
     #[allow(clippy::type_complexity)]
     fn draw(&mut self, ctx: &mut Context, screen: &mut [u8]) {
         let mut system_state: SystemState<(
-            EventReader<player::FlashLight>,
+            Res<Events<player::FlashLight>>,
+            Res<Events<player::ShakeScreen>>,
             Res<Camera>,
+            Res<PreviousCamera>,
+            Res<CameraEffects>,
+            Res<PreviousCameraEffects>,
             Res<map::Map>,
-            Query<(&components::Transform, &components::Sprite)>,
+            Res<LightMap>,
+            Res<Atmosphere>,
+            Res<GameData>,
+            Res<Hud>,
+            ResMut<TextureRegistry>,
+            Query<(
+                &components::Transform,
+                Option<&components::PreviousTransform>,
+                &components::Sprite,
+            )>,
+            Query<(&components::Player, &components::Stamina)>,
+            Query<&components::Flashlight>,
         )> = SystemState::new(&mut self.world);
 
-        let (mut event_reader, cam, map, sprite_query) = system_state.get_mut(&mut self.world);
-
-        let floor = ctx.assets.load::<Texture>("textures.floor").unwrap().read();
-        let ceil = ctx.assets.load::<Texture>("textures.ceil").unwrap().read();
-
-        let cam_pos_x = cam.pos.x;
-        let cam_pos_y = cam.pos.y;
+        let (
+            flash_events,
+            shake_events,
+            cam,
+            prev_cam,
+            effects,
+            prev_effects,
+            map,
+            lightmap,
+            atmosphere,
+            data,
+            hud,
+            mut textures,
+            sprite_query,
+            player_query,
+            flashlight_query,
+        ) = system_state.get_mut(&mut self.world);
+
+        // Draw in between the last two updates so movement stays smooth at any frame rate
+        let mut cam = prev_cam.0.lerp(&cam, ctx.alpha);
+        cam.fit_plane(ctx.display.plane(ctx.size));
+        let effects = prev_effects.0.lerp(&effects, ctx.alpha);
+        let [bob, kick, lean] = effects.modifiers();
+        camera::apply(&mut cam, &[bob, kick, lean, &CameraShake::new(&self.shake)]);
+
+        let (width, height) = (ctx.size.x as usize, ctx.size.y as usize);
+        if self.renderer.size() != (width, height) {
+            self.renderer.resize(width, height);
+        }
 
-        for event in event_reader.iter() {
+        for event in self.flash_reader.iter(&flash_events) {
             self.light_intensity = event.intesity;
             self.light_duration = event.duration;
         }
 
-        if self.light_duration != 0 {
-            self.light_intensity = lerp(self.light_intensity, 1., 1. / self.light_duration as f32);
-            self.light_duration -= 1;
+        if self.light_duration > 0. {
+            let fade = (ctx.frame_time / self.light_duration).min(1.);
+            self.light_intensity = lerp(self.light_intensity, 1., fade);
+            self.light_duration -= ctx.frame_time;
         }
 
-        // draw map first
-
-        // raycast
-        // followed this tutorial lmao https://lodev.org/cgtutor/raycasting.html
-
-        // floor + ceiling
-        for y in 0..HEIGHT {
-            let ray_0 = cam.dir - cam.plane;
-            let ray_1 = cam.dir + cam.plane;
-
-            let cur_y_pos = y as i32 - HEIGHT as i32 / 2;
-            let vertical_pos = 0.5 * HEIGHT as f32;
-            let row_dist = vertical_pos / cur_y_pos as f32;
-
-            let step = row_dist * (ray_1 - ray_0) / WIDTH as f32;
-            let mut floor_pos = vec2(
-                cam_pos_x + row_dist * ray_0.x,
-                cam_pos_y + row_dist * ray_0.y,
-            );
-
-            for x in 0..WIDTH {
-                let cell = floor_pos.as_uvec2();
-
-                let tex_coords = uvec2(
-                    (floor.width() as f32 * (floor_pos.x - cell.x as f32)) as u32
-                        & (floor.width() as f32 - 1.) as u32,
-                    (floor.height() as f32 * (floor_pos.y - cell.y as f32)) as u32
-                        & (floor.height() as f32 - 1.) as u32,
-                );
-                floor_pos += step;
-
-                let idx = idx(tex_coords.x * 4, tex_coords.y * 4, floor.width());
-                let dist = (row_dist * DARKNESS / self.light_intensity / 0.5).max(1.) as u8;
-
-                // floor
-                {
-                    let mut rgba = floor.pixel(idx).slice();
-
-                    rgba.iter_mut().take(3).for_each(|val| {
-                        *val = *val / 2 / dist;
-                    });
-
-                    let i = x * 4 + y * WIDTH * 4;
-                    screen[i..i + 4].copy_from_slice(&rgba);
-                }
-
-                // ceiling
-                {
-                    let mut rgba = ceil.pixel(idx).slice();
-                    rgba.iter_mut().take(3).for_each(|val| {
-                        *val = *val / 2 / dist;
-                    });
-
-                    let i = x * 4 + (HEIGHT - y - 1) * WIDTH * 4;
-                    screen[i..i + 4].copy_from_slice(&rgba);
-                }
-            }
+        for event in self.shake_reader.iter(&shake_events) {
+            self.shake.add(event.trauma);
         }
+        self.shake.update(ctx.frame_time);
+
+        let sprites: Vec<(components::Transform, &components::Sprite)> = sprite_query
+            .iter()
+            .map(|(trans, prev, sprite)| {
+                let trans = prev.map_or(*trans, |prev| prev.lerp(trans, ctx.alpha));
+                (trans, sprite)
+            })
+            .collect();
+
+        let light = Lighting {
+            intensity: self.light_intensity,
+            darkness: atmosphere.darkness,
+            fog: atmosphere.fog,
+            lightmap: Some(&lightmap),
+            flashlight: flashlight_query
+                .iter()
+                .find_map(|flashlight| player::flashlight_cone(cam.pos, cam.dir, flashlight)),
+            ..Default::default()
+        };
+        // Sprites may have picked up textures that weren't needed before
+        textures.load(&ctx.assets);
+        self.renderer
+            .render(screen, &map, &cam, &sprites, &textures, &light);
 
-        // wall
-        for x in 0..WIDTH {
-            let mut tile_pos = cam.pos.as_ivec2();
-
-            // cam coordinates in range of -1 to 1
-            let cam_x = 2. * x as f32 / WIDTH as f32 - 1.;
-            let ray_x = cam.dir.x + cam.plane.x * cam_x;
-            let ray_y = cam.dir.y + cam.plane.y * cam_x;
-
-            let mut side_dist_x;
-            let mut side_dist_y;
-
-            let delta_dist_x = (1. / ray_x).abs();
-            let delta_dist_y = (1. / ray_y).abs();
-
-            let step_dir_x;
-            let step_dir_y;
-            let mut side = false;
-
-            // calculate step and side distances
-            if ray_x < 0. {
-                step_dir_x = -1;
-                side_dist_x = (cam_pos_x - tile_pos.x as f32) * delta_dist_x;
-            } else {
-                step_dir_x = 1;
-                side_dist_x = (tile_pos.x as f32 + 1. - cam_pos_x) * delta_dist_x;
-            }
-            if ray_y < 0. {
-                step_dir_y = -1;
-                side_dist_y = (cam_pos_y - tile_pos.y as f32) * delta_dist_y;
-            } else {
-                step_dir_y = 1;
-                side_dist_y = (tile_pos.y as f32 + 1. - cam_pos_y) * delta_dist_y;
-            }
-
-            let mut hit = false;
-
-            // DDA
-            while !hit {
-                if side_dist_x < side_dist_y {
-                    side_dist_x += delta_dist_x;
-                    tile_pos.x += step_dir_x;
-                    side = false;
-                } else {
-                    side_dist_y += delta_dist_y;
-                    tile_pos.y += step_dir_y;
-                    side = true;
-                }
-
-                if tile_pos.x.is_negative() || tile_pos.y.is_negative() {
-                    return;
-                }
-                if let Some(tile) = map.get_tile(tile_pos.x as u32, tile_pos.y as u32) {
-                    hit = *tile != map::Tile::Empty;
-                }
-            }
-
-            let perp_wall_dist = if !side {
-                side_dist_x - delta_dist_x
-            } else {
-                side_dist_y - delta_dist_y
-            };
-            let wall_height = (HEIGHT as f32 / perp_wall_dist) as i32;
-            let draw_start = (-wall_height / 2 + HEIGHT as i32 / 2).max(0);
-            let draw_end = (wall_height / 2 + HEIGHT as i32 / 2).min(HEIGHT as i32);
-
-            let dist = (HEIGHT as f32 * DARKNESS / self.light_intensity / wall_height as f32)
-                .max(1.) as u8;
-
-            let tile = map
-                .get_tile(tile_pos.x as u32, tile_pos.y as u32)
-                .expect("tile should have been found already");
-            let tex_path = format!("textures.{}", tile_to_texture(*tile));
-            let tex = ctx
-                .assets
-                .load::<Texture>(tex_path.as_str())
-                .expect("Failed to find texture from path")
-                .read();
-
-            // texture stuff
-            let mut wall_x = if !side {
-                cam_pos_y + perp_wall_dist * ray_y
-            } else {
-                cam_pos_x + perp_wall_dist * ray_x
-            };
-            wall_x -= wall_x.floor();
-
-            // texture x coordinate
-            let mut tex_x = (wall_x * tex.width() as f32) as u32;
-            if (!side && ray_x > 0.) || (side && ray_y < 0.) {
-                tex_x = tex.width() - tex_x - 1;
-            }
-
-            let step = tex.height() as f32 / wall_height as f32;
-            let mut tex_pos = (draw_start - HEIGHT as i32 / 2 + wall_height / 2) as f32 * step;
-
-            for y in draw_start..draw_end {
-                let tex_y = tex_pos as u32 & (tex.height() - 1);
-                tex_pos += step;
-
-                // Multiply tex coordinates by 4 to ensure index rgba is in correct order
-                let idx = idx(tex_x * 4, tex_y * 4, tex.width());
-                let mut rgba = tex.pixel(idx).slice();
-
-                rgba.iter_mut().take(3).for_each(|val| {
-                    if side {
-                        *val /= 2;
-                    }
-
-                    *val /= dist;
-                });
-
-                let i = x * 4 + y as usize * WIDTH * 4;
-                screen[i..i + 4].copy_from_slice(&rgba);
-            }
-
-            // sprites
-            self.z_buffer[x] = perp_wall_dist;
+        if let Some(grade) = &self.grade {
+            grade.apply(screen);
         }
-
-        let mut sprites: Vec<(&components::Transform, &components::Sprite)> =
-            sprite_query.iter().collect();
-        sprites.sort_by(|(trans_a, _), (trans_b, _)| {
-            // Sort based on the sprite's distance to camera (far to close)
-            let dist_a = trans_a.pos.distance_squared(cam.pos);
-            dist_a
-                .total_cmp(&trans_b.pos.distance_squared(cam.pos))
-                .reverse()
-        });
-
-        sprites
-                    .iter()
-                    .for_each(|(trans, sprite)| {
-                        let Ok(tex) = ctx.assets.load::<Texture>(&format!("textures.{}", sprite.texture)) else {
-                            warn!("Could not load sprite with texture {}. Path does not exist", sprite.texture);
-                            return;
-                        };
-                        let tex = tex.read();
-
-                        // sprite position relative to camera
-                        let pos = trans.pos - cam.pos;
-                        let inverse = 1.
-                            / (cam.plane.x * cam.dir.y
-                                - cam.dir.x * cam.plane.y);
-                        let trans_x = inverse * (cam.dir.y * pos.x - cam.dir.x * pos.y);
-                        let trans_y =
-                            inverse * (-cam.plane.y * pos.x + cam.plane.x * pos.y);
-
-                        // Prevent number from being too low
-                        if trans_y.abs() < 0.001 {
-                            return;
-                        }
-
-                        let move_screen = (-sprite.height / trans_y) as i32;
-
-                        let screen_x = ((WIDTH as f32 / 2.) * (1. + trans_x / trans_y)) as i32;
-                        let sprite_height = (HEIGHT as f32 / trans_y * trans.scale.y).abs() as i32;
-                        let sprite_width = (HEIGHT as f32 / trans_y * trans.scale.x).abs() as i32;
-
-                        let draw_start = uvec2(
-                            (-sprite_width / 2 + screen_x).max(0) as u32,
-                            (-sprite_height / 2 + HEIGHT as i32 / 2 + move_screen).max(0) as u32,
-                        );
-                        let draw_end = uvec2(
-                            (((sprite_width / 2) + screen_x).max(0) as u32).min(WIDTH as u32),
-                            (sprite_height / 2 + HEIGHT as i32 / 2 + move_screen).min(HEIGHT as i32) as u32);
-
-                        let dist = (trans.pos.distance(cam.pos) * DARKNESS / self.light_intensity / 2.).max(1.) as u8;
-
-                        for x in draw_start.x..draw_end.x {
-                            let tex_x = (256 * (x as i32 - (-sprite_width / 2 + screen_x)) as u32 * tex.width() / sprite_width as u32) / 256;
-                            if !(trans_y > 0. && x < WIDTH as u32 && trans_y < self.z_buffer[x as usize]) {
-                                continue;
-                            }
-                            for y in draw_start.y..draw_end.y {
-                                let d = ((y as i32 - move_screen) * 256 - HEIGHT as i32 * 128 + sprite_height * 128) as u32;
-                                let tex_y = (d * tex.height()) / sprite_height as u32 / 256;
-                                let idx = idx(tex_x * 4, tex_y * 4, tex.width());
-                                let color = tex.pixel(idx);
-
-                                if color.a == 0 {
-                                    continue;
-                                }
-
-                                let i = x as usize * 4 + y as usize * WIDTH * 4;
-
-                                let mut prev_color = Color::from(&screen[i..i + 4]);
-                                prev_color.blend(color);
-
-                                let mut slice = prev_color.slice();
-
-                                slice.iter_mut().take(3).for_each(|val| {
-                                    *val /= dist;
-                                });
-                                screen[i..i + 4].copy_from_slice(&slice);
-                            }
-                        }
-                    });
-
-        //graphics::draw_text(screen, uvec2(WIDTH as u32 / 2, HEIGHT as u32 / 2), "A");
+        atmosphere
+            .post
+            .apply(screen, width, &self.shake, self.frames);
+        self.frames = self.frames.wrapping_add(1);
+
+        let player = player_query.iter().next();
+        let info = HudInfo {
+            hud: &hud,
+            data: &data,
+            batteries: player.map_or(0, |(player, _)| player.batteries),
+            stamina: player.map(|(_, stamina)| stamina),
+        };
+        hud::draw(screen, width, &ctx.font, &info);
     }
 }
 
@@ -604,11 +562,33 @@ where
     schedule.add_system(Events::<T>::update_system.in_base_set(CoreSet::First));
 }
 
+/// Catches what was drawn last update up with the current one, so the world holds still
+/// instead of swinging between the two while paused under another state
+fn hold_still(world: &mut World) {
+    let cam = *world.resource::<Camera>();
+    world.resource_mut::<PreviousCamera>().0 = cam;
+    let effects = *world.resource::<CameraEffects>();
+    world.resource_mut::<PreviousCameraEffects>().0 = effects;
+
+    let mut query = world.query::<(&components::Transform, &mut components::PreviousTransform)>();
+    for (trans, mut prev) in query.iter_mut(world) {
+        prev.0 = *trans;
+    }
+}
+
 fn setup_map(world: &mut World) {
-    let gen = map::MapGenerator::new(0);
+    let floor = world.resource::<GameData>().floor;
+    let gen = map::MapGenerator::new(floor as u64);
 
-    let mut system_state: SystemState<Commands> = SystemState::new(world);
-    let mut cmd = system_state.get(world);
+    let mut unread: Vec<usize> = world
+        .resource::<Notes>()
+        .unread(world.resource::<Journal>())
+        .collect();
+    unread.reverse();
+
+    let mut system_state: SystemState<(Commands, ResMut<TextureRegistry>)> =
+        SystemState::new(world);
+    let (mut cmd, mut textures) = system_state.get_mut(world);
 
     spawner::spawn_player(
         &mut cmd,
@@ -618,39 +598,209 @@ fn setup_map(world: &mut World) {
         },
     );
 
-    for (ent, spawn) in gen.entities {
-        ent.spawn(&mut cmd, spawn.as_vec2() + 0.5);
-    }
+    for (ent, spawn) in &gen.entities {
+        // Notes only get placed while there's something left to read
+        let note = match ent {
+            map::Entity::Note => match unread.pop() {
+                Some(id) => Some(components::Note { id }),
+                None => continue,
+            },
+            _ => None,
+        };
 
-    let mut monster_spawn = Vec2::NEG_ONE;
-    while monster_spawn == Vec2::NEG_ONE {
-        let x = rand::thread_rng().gen_range(0..gen.map.width());
-        let y = rand::thread_rng().gen_range(0..gen.map.height());
+        let id = ent.spawn(&mut cmd, &mut textures, spawn.as_vec2() + 0.5);
+        if let Some(note) = note {
+            cmd.entity(id).insert(note);
+        }
+    }
 
-        if gen.map.get_tile(x, y) == Some(&map::Tile::Empty) {
-            monster_spawn = vec2(x as f32, y as f32) + 0.5;
+    // Floors and ceilings the map uses, on top of the ones the renderer always needs
+    for look in gen.map.looks() {
+        textures.id(&look.floor);
+        if let Some(ceiling) = &look.ceiling {
+            textures.id(ceiling);
         }
     }
 
-    spawner::spawn_monster(
-        &mut cmd,
-        components::Transform {
-            pos: monster_spawn,
-            ..Default::default()
-        },
-    );
+    for pos in spawner::monster_spawns(&gen, &spawner::MONSTER_RULES, floor) {
+        spawner::spawn_monster(
+            &mut cmd,
+            &mut textures,
+            components::Transform {
+                pos,
+                ..Default::default()
+            },
+        );
+    }
 
     // Since we used commands, we need to apply them to the world
     system_state.apply(world);
     world.insert_resource(gen.map);
+    world.insert_resource(gen.atmosphere);
+
+    // Start at the spawn so the first frames don't sweep over from the origin
+    let cam = Camera {
+        pos: gen.spawn,
+        ..Default::default()
+    };
+    world.insert_resource(cam);
+    world.insert_resource(PreviousCamera(cam));
+}
+
+/// Takes the player down to a new floor with more monsters. Everything on the old one is
+/// left behind except the batteries they were carrying
+/// What the exit conditions sent since the last update lead to
+enum Exit {
+    Stay,
+    Descend,
+    End(&'static str),
+}
+
+/// Reads the exit conditions sent since `reader` last looked, while on `floor`
+fn take_exit(world: &World, reader: &mut ManualEventReader<ExitCondition>, floor: u32) -> Exit {
+    let mut exit = Exit::Stay;
+    for event in reader.iter(world.resource::<Events<ExitCondition>>()) {
+        exit = match event {
+            ExitCondition::Win if floor < FLOORS => match exit {
+                Exit::End(_) => exit,
+                _ => Exit::Descend,
+            },
+            ExitCondition::Win => Exit::End("yay you win"),
+            ExitCondition::Lose => Exit::End("you are ded. not big surprise"),
+        };
+    }
+    exit
 }
 
-fn tile_to_texture(tile: map::Tile) -> &'static str {
-    use map::Tile;
+fn next_floor(world: &mut World) {
+    let batteries = world
+        .query::<&components::Player>()
+        .iter(world)
+        .map(|player| player.batteries)
+        .sum();
+
+    let entities: Vec<Entity> = world.query::<Entity>().iter(world).collect();
+    for ent in entities {
+        world.despawn(ent);
+    }
 
-    match tile {
-        Tile::Empty => "",
-        Tile::Exit => "exit",
-        _ => "wall",
+    let mut data = world.resource_mut::<GameData>();
+    data.floor += 1;
+    data.generators_required = GameData::default().generators_required;
+    let floor = data.floor;
+    info!("Going down to floor {floor}");
+
+    setup_map(world);
+    for mut player in world.query::<&mut components::Player>().iter_mut(world) {
+        player.batteries = batteries;
+    }
+    world.send_event(hud::Toast(format!("Floor {floor}")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::default();
+        world.insert_resource(GameData::default());
+        world.init_resource::<TextureRegistry>();
+        world.init_resource::<Notes>();
+        world.init_resource::<Journal>();
+        setup_map(&mut world);
+        world
+    }
+
+    fn count<T: Component>(world: &mut World) -> u32 {
+        world.query::<&T>().iter(world).count() as u32
+    }
+
+    #[test]
+    fn deeper_floors_have_more_monsters() {
+        let mut world = world();
+        assert_eq!(
+            count::<components::Monster>(&mut world),
+            spawner::MONSTER_RULES.count(1)
+        );
+
+        for mut player in world
+            .query::<&mut components::Player>()
+            .iter_mut(&mut world)
+        {
+            player.batteries = 2;
+        }
+        world.resource_mut::<GameData>().generators_required = 0;
+        next_floor(&mut world);
+
+        let data = world.resource::<GameData>();
+        assert_eq!(data.floor, 2);
+        assert_eq!(
+            data.generators_required,
+            GameData::default().generators_required
+        );
+        assert_eq!(
+            count::<components::Monster>(&mut world),
+            spawner::MONSTER_RULES.count(2)
+        );
+        assert!(spawner::MONSTER_RULES.count(2) > spawner::MONSTER_RULES.count(1));
+
+        // A fresh map with one player, still carrying what they found
+        assert_eq!(count::<components::Player>(&mut world), 1);
+        assert_eq!(count::<components::Exit>(&mut world), 1);
+        let player = world.query::<&components::Player>().single(&world);
+        assert_eq!(player.batteries, 2);
+    }
+
+    #[test]
+    fn winning_goes_down_one_floor() {
+        let mut world = world();
+        world.init_resource::<Events<ExitCondition>>();
+        let mut reader = ManualEventReader::default();
+        world.send_event(ExitCondition::Win);
+
+        // The same updates as `InGame::update`, where nothing clears events after going down
+        for _ in 0..FLOORS {
+            let floor = world.resource::<GameData>().floor;
+            if let Exit::Descend = take_exit(&world, &mut reader, floor) {
+                next_floor(&mut world);
+            }
+        }
+        assert_eq!(world.resource::<GameData>().floor, 2);
+
+        // Winning on the last floor ends the game instead
+        world.resource_mut::<GameData>().floor = FLOORS;
+        world.send_event(ExitCondition::Win);
+        assert!(matches!(
+            take_exit(&world, &mut reader, FLOORS),
+            Exit::End(_)
+        ));
+    }
+
+    #[test]
+    fn paused_world_holds_still() {
+        let mut world = world();
+        world.insert_resource(PreviousCamera::default());
+        world.init_resource::<CameraEffects>();
+        world.init_resource::<PreviousCameraEffects>();
+        world.resource_mut::<Camera>().pos += 1.;
+        for mut trans in world
+            .query::<&mut components::Transform>()
+            .iter_mut(&mut world)
+        {
+            trans.pos += 1.;
+        }
+
+        hold_still(&mut world);
+
+        // Drawing at any point between the updates shows the same thing
+        let cam = *world.resource::<Camera>();
+        let prev = world.resource::<PreviousCamera>().0;
+        assert_eq!(prev.lerp(&cam, 0.3).pos, cam.pos);
+        for (trans, prev) in world
+            .query::<(&components::Transform, &components::PreviousTransform)>()
+            .iter(&world)
+        {
+            assert_eq!(prev.lerp(trans, 0.3).pos, trans.pos);
+        }
     }
 }
@@ -0,0 +1,257 @@
+use crate::{
+    font::Align,
+    graphics::{self, Color, Texture},
+    input::KeyCode,
+    notes::{Journal, NoteDef, Notes},
+    prelude::*,
+    state::State,
+    Context,
+};
+
+/// Gap between the edge of the page and what's written on it
+const PADDING: i32 = 6;
+/// Widest a page gets, so lines stay easy to read on wide screens
+const MAX_PAGE_WIDTH: u32 = 240;
+
+const SHADE: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 170,
+};
+const PAGE: Color = Color {
+    r: 28,
+    g: 26,
+    b: 22,
+    a: 235,
+};
+const TEXT_COLOR: Color = Color {
+    r: 220,
+    g: 215,
+    b: 200,
+    a: 255,
+};
+const DIM_COLOR: Color = Color {
+    r: 140,
+    g: 135,
+    b: 125,
+    a: 255,
+};
+
+/// A note being read on top of the game, which stays paused underneath
+pub struct NoteView {
+    note: NoteDef,
+    image: Option<Texture>,
+}
+
+impl NoteView {
+    pub fn new(ctx: &Context, note: NoteDef) -> Self {
+        let image = note.image.as_ref().and_then(|name| {
+            let tex = ctx.assets.load::<Texture>(&format!("textures.{name}"));
+            if tex.is_err() {
+                warn!("Could not load note image {name}");
+            }
+            tex.ok().map(|tex| tex.read().clone())
+        });
+        Self { note, image }
+    }
+}
+
+impl State for NoteView {
+    fn update(&mut self, ctx: &mut Context) {
+        if [KeyCode::E, KeyCode::Return, KeyCode::Back]
+            .into_iter()
+            .any(|key| ctx.input.pressed(key))
+        {
+            ctx.pop_state();
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context, screen: &mut [u8]) {
+        let width = ctx.size.x as usize;
+        let font = &ctx.font;
+        let line = font.line_height() as i32;
+        let (pos, size) = page(screen, width);
+        let inner = size.x.saturating_sub(PADDING as u32 * 2);
+
+        let mut y = pos.y + PADDING;
+        y += font.draw_box(
+            screen,
+            width,
+            ivec2(pos.x + PADDING, y),
+            uvec2(inner, line as u32 * 2),
+            &self.note.title,
+            TEXT_COLOR,
+            Align::Center,
+        ) as i32
+            + line / 2;
+
+        // Images get at most a third of the page, keeping their shape
+        if let Some(image) = &self.image {
+            let fit = (size.y / 3).min(inner) as f32 / image.width().max(image.height()) as f32;
+            let image_size = (uvec2(image.width(), image.height()).as_vec2() * fit).as_uvec2();
+            let x = pos.x + (size.x as i32 - image_size.x as i32) / 2;
+            graphics::draw_texture(screen, width, ivec2(x, y), image_size, image);
+            y += image_size.y as i32 + line / 2;
+        }
+
+        let footer = pos.y + size.y as i32 - PADDING - line;
+        font.draw_box(
+            screen,
+            width,
+            ivec2(pos.x + PADDING, y),
+            uvec2(inner, (footer - y).max(0) as u32),
+            &self.note.body,
+            TEXT_COLOR,
+            Align::Left,
+        );
+        font.draw_box(
+            screen,
+            width,
+            ivec2(pos.x + PADDING, footer),
+            uvec2(inner, line as u32),
+            "E: close",
+            DIM_COLOR,
+            Align::Right,
+        );
+    }
+
+    fn transparent(&self) -> bool {
+        true
+    }
+}
+
+/// List of the notes read so far, any of which can be opened again
+pub struct JournalView {
+    notes: Vec<NoteDef>,
+    /// How many notes there are to find in total
+    total: usize,
+    selected: usize,
+}
+
+impl JournalView {
+    pub fn new(notes: &Notes, journal: &Journal) -> Self {
+        Self {
+            notes: journal
+                .read
+                .iter()
+                .filter_map(|id| notes.0.get(*id).cloned())
+                .collect(),
+            total: notes.0.len(),
+            selected: 0,
+        }
+    }
+}
+
+impl State for JournalView {
+    fn update(&mut self, ctx: &mut Context) {
+        if ctx.input.pressed(KeyCode::J) || ctx.input.pressed(KeyCode::Back) {
+            ctx.pop_state();
+            return;
+        }
+
+        let count = self.notes.len();
+        if count == 0 {
+            return;
+        }
+        if ctx.input.pressed(KeyCode::Up) || ctx.input.pressed(KeyCode::W) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if ctx.input.pressed(KeyCode::Down) || ctx.input.pressed(KeyCode::S) {
+            self.selected = (self.selected + 1) % count;
+        }
+        if ctx.input.pressed(KeyCode::E) || ctx.input.pressed(KeyCode::Return) {
+            let view = NoteView::new(ctx, self.notes[self.selected].clone());
+            ctx.push_state(Box::new(view));
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context, screen: &mut [u8]) {
+        let width = ctx.size.x as usize;
+        let font = &ctx.font;
+        let line = font.line_height() as i32;
+        let (pos, size) = page(screen, width);
+        let inner = size.x.saturating_sub(PADDING as u32 * 2);
+        let x = pos.x + PADDING;
+
+        let title = format!("Journal - {}/{} notes", self.notes.len(), self.total);
+        font.draw_box(
+            screen,
+            width,
+            ivec2(x, pos.y + PADDING),
+            uvec2(inner, line as u32),
+            &title,
+            TEXT_COLOR,
+            Align::Center,
+        );
+
+        let top = pos.y + PADDING + line * 2;
+        let footer = pos.y + size.y as i32 - PADDING - line;
+        if self.notes.is_empty() {
+            font.draw_box(
+                screen,
+                width,
+                ivec2(x, top),
+                uvec2(inner, line as u32),
+                "Nothing yet",
+                DIM_COLOR,
+                Align::Center,
+            );
+        }
+
+        // Scroll so the selected note is always in view
+        let rows = ((footer - top) / line).max(1) as usize;
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, note) in self.notes.iter().enumerate().skip(first).take(rows) {
+            let (text, color) = if i == self.selected {
+                (format!("> {}", note.title), TEXT_COLOR)
+            } else {
+                (format!("  {}", note.title), DIM_COLOR)
+            };
+            let y = top + (i - first) as i32 * line;
+            font.draw_box(
+                screen,
+                width,
+                ivec2(x, y),
+                uvec2(inner, line as u32),
+                &text,
+                color,
+                Align::Left,
+            );
+        }
+
+        font.draw_box(
+            screen,
+            width,
+            ivec2(x, footer),
+            uvec2(inner, line as u32),
+            "E: read  J: close",
+            DIM_COLOR,
+            Align::Right,
+        );
+    }
+
+    fn transparent(&self) -> bool {
+        true
+    }
+}
+
+/// Shades the whole screen and draws an empty page in the middle, returning where it is
+fn page(screen: &mut [u8], width: usize) -> (IVec2, UVec2) {
+    let height = (screen.len() / 4 / width) as u32;
+    graphics::fill_rect(
+        screen,
+        width,
+        UVec2::ZERO,
+        uvec2(width as u32, height),
+        SHADE,
+    );
+
+    let size = uvec2(
+        (width as u32).saturating_sub(16).min(MAX_PAGE_WIDTH),
+        height.saturating_sub(16),
+    );
+    let pos = (uvec2(width as u32, height) - size) / 2;
+    graphics::fill_rect(screen, width, pos, size, PAGE);
+    (pos.as_ivec2(), size)
+}
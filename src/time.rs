@@ -0,0 +1,46 @@
+use bevy_ecs::prelude::*;
+
+use crate::{components, state::game::CoreSet};
+
+/// Fixed step the simulation advances by every update, independent of how often frames are drawn
+#[derive(Resource, Debug)]
+pub struct Time {
+    step: f32,
+    elapsed: f32,
+}
+
+impl Time {
+    pub fn new(updates_per_second: u32) -> Self {
+        Self {
+            step: 1. / updates_per_second as f32,
+            elapsed: 0.,
+        }
+    }
+
+    /// Seconds simulated each update
+    pub fn delta(&self) -> f32 {
+        self.step
+    }
+
+    /// Seconds simulated since the world was created
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+}
+
+pub fn add_to_world(schedule: &mut Schedule, world: &mut World) {
+    world.insert_resource(Time::new(crate::UPDATES_PER_SECOND));
+    schedule.add_systems((advance, store_previous_transforms).in_base_set(CoreSet::First));
+}
+
+fn advance(mut time: ResMut<Time>) {
+    time.elapsed += time.step;
+}
+
+fn store_previous_transforms(
+    mut query: Query<(&components::Transform, &mut components::PreviousTransform)>,
+) {
+    for (trans, mut prev) in query.iter_mut() {
+        prev.0 = *trans;
+    }
+}